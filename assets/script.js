@@ -4,8 +4,14 @@ class WebSocketClient {
 
     newPoint;
 
-    constructor(newPoint) {
+    updatePoint;
+
+    removePoint;
+
+    constructor(newPoint, updatePoint, removePoint) {
         this.newPoint = newPoint;
+        this.updatePoint = updatePoint;
+        this.removePoint = removePoint;
         this._generate();
     }
 
@@ -32,12 +38,29 @@ class WebSocketClient {
             console.log("WebSocket message");
             const data = JSON.parse(event.data);
 
+            const id = data.id;
+            const call = data.call;
             const band = data.band;
             const latitude = data.latitude;
             const longitude = data.longitude;
-            console.log(`Latitude: ${latitude}, longitude: ${longitude}, band: ${band}`);
+            const duplicate = data.duplicate === true;
+            const quality = data.location_quality;
+            const path = data.path;
 
-            this.newPoint(latitude, longitude, band);
+            switch (data.event) {
+                case 'add':
+                    console.log(`Add ${id}: latitude: ${latitude}, longitude: ${longitude}, band: ${band}, quality: ${quality}`);
+                    this.newPoint(id, call, latitude, longitude, band, duplicate, quality, path);
+                    break;
+                case 'update':
+                    console.log(`Update ${id}: latitude: ${latitude}, longitude: ${longitude}, band: ${band}, quality: ${quality}`);
+                    this.updatePoint(id, call, latitude, longitude, band, duplicate, quality, path);
+                    break;
+                case 'remove':
+                    console.log(`Remove ${id}`);
+                    this.removePoint(id);
+                    break;
+            }
         }
     }
 
@@ -59,18 +82,90 @@ class PointHandler {
         this.map = map;
     }
 
-    addPoint(point) {
+    addPoint(id, point) {
         const [marker, geodesic] = point;
         marker.addTo(this.map);
         geodesic.addTo(this.map);
 
-        this.points.push(point);
+        this.points.push([id, point]);
 
         while (this.points.length > 10) {
-            const [marker, geodesic] = this.points.shift();
-            this.map.removeLayer(marker);
-            this.map.removeLayer(geodesic);
+            const [_, point] = this.points.shift();
+            this._removeLayers(point);
+        }
+    }
+
+    updatePoint(id, point) {
+        const index = this.points.findIndex(([pointId, _]) => id != null && pointId === id);
+        if (index < 0) {
+            this.addPoint(id, point);
+            return;
+        }
+
+        const [marker, geodesic] = point;
+        marker.addTo(this.map);
+        geodesic.addTo(this.map);
+
+        this._removeLayers(this.points[index][1]);
+        this.points[index] = [id, point];
+    }
+
+    removePoint(id) {
+        const index = this.points.findIndex(([pointId, _]) => pointId === id);
+        if (index < 0) {
+            return;
+        }
+
+        const [[_, point]] = this.points.splice(index, 1);
+        this._removeLayers(point);
+    }
+
+    _removeLayers(point) {
+        const [marker, geodesic] = point;
+        this.map.removeLayer(marker);
+        this.map.removeLayer(geodesic);
+    }
+}
+
+class UnlocatedHandler {
+    container;
+    list;
+    entries = [];
+
+    constructor(containerId) {
+        this.container = document.getElementById(containerId);
+        this.list = this.container.querySelector('ul');
+    }
+
+    addEntry(id, call, band) {
+        this.removeEntry(id);
+
+        const item = document.createElement('li');
+        item.textContent = `${call} (${band})`;
+        this.list.prepend(item);
+        this.entries.push([id, item]);
+
+        while (this.entries.length > 10) {
+            const [_, item] = this.entries.shift();
+            item.remove();
         }
+
+        this._refresh();
+    }
+
+    removeEntry(id) {
+        const index = this.entries.findIndex(([entryId, _]) => id != null && entryId === id);
+        if (index < 0) {
+            return;
+        }
+
+        const [[_, item]] = this.entries.splice(index, 1);
+        item.remove();
+        this._refresh();
+    }
+
+    _refresh() {
+        this.container.hidden = this.entries.length === 0;
     }
 }
 
@@ -96,19 +191,41 @@ async function retrieveHomePoint() {
     return new L.latLng(response_body.latitude, response_body.longitude);
 }
 
-function generateMarkerGeodesic(pointFrom, pointTo, geodesicColor) {
+function generateMarkerGeodesic(pointFrom, pointTo, geodesicColor, duplicate, quality, tooltip) {
     const marker = L.marker(pointTo, {
-        opacity: 1
+        opacity: duplicate ? 0.5 : quality === 'dxcc_centroid' ? 0.7 : 1
     });
+    marker.bindTooltip(tooltip);
 
     const geodesic = L.geodesic([pointFrom, pointTo], {
         weight: 1,
-        color: geodesicColor
+        color: geodesicColor,
+        dashArray: computeDashArrayByQuality(quality)
     });
 
     return [marker, geodesic];
 }
 
+function computeTooltip(call, path) {
+    if (path == null) {
+        return call;
+    }
+
+    const label = path.long_path ? 'LP' : 'SP';
+    return `${call} - ${Math.round(path.distance_km)} km / ${Math.round(path.distance_mi)} mi - ${Math.round(path.bearing)}° ${label}`;
+}
+
+function computeDashArrayByQuality(quality) {
+    switch (quality) {
+        case 'grid':
+            return '6 4';
+        case 'dxcc_centroid':
+            return '2 6';
+        default:
+            return null;
+    }
+}
+
 function computeColorByBand(band) {
     switch (band) {
         case '10':
@@ -135,13 +252,38 @@ async function initialize() {
     L.marker(pointHome).addTo(map);
 
     const pointsHandler = new PointHandler(map);
+    const unlocatedHandler = new UnlocatedHandler('unlocated');
 
-    new WebSocketClient((latitude, longitude, band) => {
+    const generatePoint = (call, latitude, longitude, band, duplicate, quality, path) => {
         const point = new L.latLng(latitude, longitude);
         const color = computeColorByBand(band);
-        const [marker, geodesic] = generateMarkerGeodesic(pointHome, point, color);
-        pointsHandler.addPoint([marker, geodesic]);
-    });
+        const tooltip = computeTooltip(call, path);
+        return generateMarkerGeodesic(pointHome, point, color, duplicate, quality, tooltip);
+    };
+
+    const isUnlocated = (latitude, longitude) => latitude == null || longitude == null;
+
+    new WebSocketClient(
+        (id, call, latitude, longitude, band, duplicate, quality, path) => {
+            if (isUnlocated(latitude, longitude)) {
+                unlocatedHandler.addEntry(id, call, band);
+                return;
+            }
+            pointsHandler.addPoint(id, generatePoint(call, latitude, longitude, band, duplicate, quality, path));
+        },
+        (id, call, latitude, longitude, band, duplicate, quality, path) => {
+            if (isUnlocated(latitude, longitude)) {
+                pointsHandler.removePoint(id);
+                unlocatedHandler.addEntry(id, call, band);
+                return;
+            }
+            unlocatedHandler.removeEntry(id);
+            pointsHandler.updatePoint(id, generatePoint(call, latitude, longitude, band, duplicate, quality, path));
+        },
+        (id) => {
+            pointsHandler.removePoint(id);
+            unlocatedHandler.removeEntry(id);
+        });
 }
 
 initialize().then(r => console.log("Initialized"));
@@ -1,7 +1,11 @@
 #!/usr/bin/python3
 
+import hashlib
+import hmac
+import os
 import socket
 import sys
+import uuid
 
 if __name__ == "__main__":
     for callsign in sys.argv[1:]:
@@ -31,10 +35,19 @@ if __name__ == "__main__":
             b'<duplicate>True</duplicate>'
             b'<stationname></stationname>'
             b'<points>0</points>'
-            b'<id>123456789</id>'
+            b'<id>'
+        )
+        payload += uuid.uuid4().hex.encode()
+        payload += (
+            b'</id>'
             b'</contactinfo>'
         )
 
+        secret = os.environ.get("LIVE_QSO_MAP_HMAC_SECRET")
+        if secret:
+            signature = hmac.new(secret.encode(), payload, hashlib.sha256).hexdigest()
+            payload = signature.encode() + b'\n' + payload
+
         sck = socket.socket(socket.AF_INET, socket.SOCK_DGRAM)
         sck.sendto(payload, ("127.0.0.1", 12060))
         sck.close()
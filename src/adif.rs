@@ -0,0 +1,335 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::models::Point;
+use crate::receiver::{band_from_frequency, ContactInfo};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+pub type Record = HashMap<String, String>;
+
+#[derive(Debug, PartialEq)]
+pub enum ADIFError {
+    MalformedTag(String),
+    MissingField(String),
+    FieldParsing(String, String),
+}
+
+impl Display for ADIFError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ADIFError::MalformedTag(e) => {
+                write!(f, "Malformed tag: {}", e)
+            }
+            ADIFError::MissingField(e) => {
+                write!(f, "Missing field: {}", e)
+            }
+            ADIFError::FieldParsing(field, value) => {
+                write!(f, "Invalid value for field {}: {}", field, value)
+            }
+        }
+    }
+}
+
+pub fn parse_records(payload: &str) -> Result<Vec<Record>, ADIFError> {
+    let data = payload.as_bytes();
+    let mut records = Vec::new();
+    let mut record = Record::new();
+    let mut pos = 0;
+
+    while let Some(start) = find_byte(data, pos, b'<') {
+        let end = find_byte(data, start, b'>').ok_or(ADIFError::MalformedTag(
+            String::from_utf8_lossy(&data[start..]).to_string(),
+        ))?;
+        let tag = String::from_utf8_lossy(&data[start + 1..end]).to_string();
+        pos = end + 1;
+
+        let mut parts = tag.split(':');
+        let name = parts.next().unwrap_or_default().trim().to_uppercase();
+
+        match name.as_str() {
+            "EOH" => {
+                record.clear();
+                continue;
+            }
+            "EOR" => {
+                if !record.is_empty() {
+                    records.push(std::mem::take(&mut record));
+                }
+                continue;
+            }
+            _ => {}
+        }
+
+        let length: usize = match parts.next() {
+            Some(length) => length
+                .trim()
+                .parse()
+                .map_err(|_| ADIFError::MalformedTag(tag.clone()))?,
+            None => continue,
+        };
+
+        let value_end = (pos + length).min(data.len());
+        let value = String::from_utf8_lossy(&data[pos..value_end])
+            .trim()
+            .to_string();
+        pos = value_end;
+
+        if !value.is_empty() {
+            record.insert(name, value);
+        }
+    }
+
+    if !record.is_empty() {
+        records.push(record);
+    }
+
+    Ok(records)
+}
+
+pub fn records_end(data: &[u8]) -> Option<usize> {
+    let mut records_end = None;
+    let mut pos = 0;
+
+    while let Some(start) = find_byte(data, pos, b'<') {
+        let end = match find_byte(data, start, b'>') {
+            Some(end) => end,
+            None => break,
+        };
+        pos = end + 1;
+
+        let mut parts = data[start + 1..end].split(|&b| b == b':');
+        let name = parts.next().unwrap_or_default().trim_ascii();
+        if name.eq_ignore_ascii_case(b"EOR") {
+            records_end = Some(pos);
+            continue;
+        }
+
+        let length = parts
+            .next()
+            .and_then(|length| std::str::from_utf8(length).ok())
+            .and_then(|length| length.trim().parse::<usize>().ok());
+        if let Some(length) = length {
+            if pos + length > data.len() {
+                break;
+            }
+            pos += length;
+        }
+    }
+
+    records_end
+}
+
+pub fn contact_info_from_record(record: &Record, logger: &str) -> Result<ContactInfo, ADIFError> {
+    let call = field(record, "CALL")
+        .ok_or(ADIFError::MissingField("CALL".to_string()))?
+        .to_uppercase();
+
+    let frequency = parse_frequency(record, "FREQ")?;
+    let rx_frequency = parse_frequency(record, "FREQ_RX")?;
+
+    let band = match field(record, "BAND") {
+        Some(band) => normalize_band(band),
+        None => frequency
+            .map(band_from_frequency)
+            .ok_or(ADIFError::MissingField("BAND".to_string()))?,
+    };
+
+    let location = match (field(record, "LAT"), field(record, "LON")) {
+        (Some(latitude), Some(longitude)) => Some(Point {
+            latitude: parse_coordinate("LAT", latitude)?,
+            longitude: parse_coordinate("LON", longitude)?,
+        }),
+        _ => None,
+    };
+
+    let mut contact_info = ContactInfo {
+        id: None,
+        source: None,
+        logger: Some(logger.to_string()),
+        contest_name: field(record, "CONTEST_ID").map(|v| v.to_string()),
+        timestamp: parse_timestamp(field(record, "QSO_DATE"), field(record, "TIME_ON"))?,
+        my_call: field(record, "STATION_CALLSIGN").map(|v| v.to_uppercase()),
+        operator: field(record, "OPERATOR").map(|v| v.to_uppercase()),
+        station_name: None,
+        call,
+        band,
+        mode: field(record, "MODE").map(|v| v.to_uppercase()),
+        tx_frequency: frequency,
+        rx_frequency,
+        country_prefix: None,
+        wpx_prefix: field(record, "PFX").map(|v| v.to_uppercase()),
+        grid: field(record, "GRIDSQUARE").map(|v| v.to_uppercase()),
+        location,
+        sent_rst: field(record, "RST_SENT").map(|v| v.to_string()),
+        received_rst: field(record, "RST_RCVD").map(|v| v.to_string()),
+        serial_number: field(record, "SRX").and_then(|v| v.parse().ok()),
+        exchange1: field(record, "SRX_STRING").map(|v| v.to_string()),
+        exchange2: None,
+        exchange3: None,
+        duplicate: false,
+        long_path: field(record, "ANT_PATH").is_some_and(|v| v.eq_ignore_ascii_case("L")),
+        points: None,
+    };
+    contact_info.id = contact_info.synthetic_id();
+
+    Ok(contact_info)
+}
+
+fn field<'a>(record: &'a Record, name: &str) -> Option<&'a str> {
+    record.get(name).map(|v| v.as_str())
+}
+
+fn parse_frequency(record: &Record, name: &str) -> Result<Option<u64>, ADIFError> {
+    field(record, name)
+        .map(|f| {
+            f.parse::<f64>()
+                .map(|mhz| (mhz * 1_000_000.0).round() as u64)
+                .map_err(|_| ADIFError::FieldParsing(name.to_string(), f.to_string()))
+        })
+        .transpose()
+}
+
+fn parse_coordinate(name: &str, value: &str) -> Result<f64, ADIFError> {
+    let error = || ADIFError::FieldParsing(name.to_string(), value.to_string());
+
+    let (sign, rest) = match value.chars().next().map(|c| c.to_ascii_uppercase()) {
+        Some('N') | Some('E') => (1.0, &value[1..]),
+        Some('S') | Some('W') => (-1.0, &value[1..]),
+        _ => return Err(error()),
+    };
+    let (degrees, minutes) = rest.trim().split_once(' ').ok_or_else(error)?;
+    let degrees: f64 = degrees.parse().map_err(|_| error())?;
+    let minutes: f64 = minutes.trim().parse().map_err(|_| error())?;
+
+    if minutes >= 60.0 {
+        return Err(error());
+    }
+
+    Ok(sign * (degrees + minutes / 60.0))
+}
+
+fn normalize_band(band: &str) -> String {
+    let band = band.to_lowercase();
+    match band.strip_suffix('m') {
+        Some(metres) if !metres.ends_with('c') => metres.to_string(),
+        _ => band,
+    }
+}
+
+fn parse_timestamp(
+    date: Option<&str>,
+    time: Option<&str>,
+) -> Result<Option<DateTime<Utc>>, ADIFError> {
+    let date = match date {
+        Some(date) => NaiveDate::parse_from_str(date, "%Y%m%d")
+            .map_err(|_| ADIFError::FieldParsing("QSO_DATE".to_string(), date.to_string()))?,
+        None => return Ok(None),
+    };
+
+    let time = match time {
+        Some(time) => {
+            let format = if time.len() == 4 { "%H%M" } else { "%H%M%S" };
+            NaiveTime::parse_from_str(time, format)
+                .map_err(|_| ADIFError::FieldParsing("TIME_ON".to_string(), time.to_string()))?
+        }
+        None => NaiveTime::MIN,
+    };
+
+    Ok(Some(date.and_time(time).and_utc()))
+}
+
+fn find_byte(data: &[u8], from: usize, byte: u8) -> Option<usize> {
+    data.get(from..)?
+        .iter()
+        .position(|&b| b == byte)
+        .map(|p| p + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::adif::{contact_info_from_record, parse_records, records_end};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_parse_records() {
+        let input = "Generated by WSJT-X
+<adif_ver:5>3.1.0 <programid:6>WSJT-X <EOH>
+<call:4>W1AW <gridsquare:4>FN31 <mode:3>FT8 <rst_sent:3>-10 <rst_rcvd:3>-12
+<qso_date:8>20241024 <time_on:6>090015 <band:3>20m <freq:9>14.074512
+<station_callsign:6>IS0GVH <my_gridsquare:6>JM49NA <eor>
+<CALL:5>K1ABC <BAND:4>70CM <MODE:2>CW <QSO_DATE:8>20241024 <TIME_ON:4>0901 <ANT_PATH:1>L <EOR>";
+
+        let records = parse_records(input).unwrap();
+        assert_eq!(records.len(), 2);
+
+        let actual = contact_info_from_record(&records[0], "WSJT-X").unwrap();
+        assert_eq!(actual.call, "W1AW");
+        assert_eq!(actual.band, "20");
+        assert_eq!(actual.mode, Some("FT8".to_string()));
+        assert_eq!(actual.grid, Some("FN31".to_string()));
+        assert_eq!(actual.tx_frequency, Some(14_074_512));
+        assert_eq!(actual.my_call, Some("IS0GVH".to_string()));
+        assert!(!actual.long_path);
+        assert_eq!(
+            actual.timestamp,
+            Some(Utc.with_ymd_and_hms(2024, 10, 24, 9, 0, 15).unwrap())
+        );
+
+        let actual = contact_info_from_record(&records[1], "WSJT-X").unwrap();
+        assert_eq!(actual.call, "K1ABC");
+        assert_eq!(actual.band, "70cm");
+        assert!(actual.long_path);
+        assert_eq!(
+            actual.timestamp,
+            Some(Utc.with_ymd_and_hms(2024, 10, 24, 9, 1, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_records_band_from_frequency() {
+        let input = "<call:4>W1AW <freq:5>7.074 <eor>";
+
+        let records = parse_records(input).unwrap();
+        let actual = contact_info_from_record(&records[0], "Test").unwrap();
+
+        assert_eq!(actual.band, "40");
+    }
+
+    #[test]
+    fn test_parse_records_missing_call() {
+        let input = "<band:3>20m <eor>";
+
+        let records = parse_records(input).unwrap();
+
+        assert!(contact_info_from_record(&records[0], "Test").is_err());
+    }
+
+    #[test]
+    fn test_records_end() {
+        let input =
+            b"<CALL:4>W1AW <COMMENT:9>See <EOR> <EOR>\n<CALL:5>K1ABC <COMMENT:12>Moved <eor>";
+
+        assert_eq!(records_end(input), Some(39));
+        assert_eq!(records_end(&input[..30]), None);
+        assert_eq!(records_end(b"<CALL:4>W1AW <EOR"), None);
+
+        let records = parse_records(std::str::from_utf8(&input[..39]).unwrap()).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].get("COMMENT"), Some(&"See <EOR>".to_string()));
+    }
+}
@@ -0,0 +1,236 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::receiver::{band_from_frequency, ContactInfo};
+use chrono::NaiveDateTime;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq)]
+pub enum CabrilloError {
+    MalformedLine(String),
+    FieldParsing(String, String),
+}
+
+impl Display for CabrilloError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CabrilloError::MalformedLine(e) => {
+                write!(f, "Malformed line: {}", e)
+            }
+            CabrilloError::FieldParsing(field, value) => {
+                write!(f, "Invalid value for field {}: {}", field, value)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Header {
+    pub callsign: Option<String>,
+    pub contest: Option<String>,
+    pub operators: Option<String>,
+}
+
+pub fn parse_line(line: &str, header: &mut Header) -> Result<Option<ContactInfo>, CabrilloError> {
+    let (tag, value) = match line.split_once(':') {
+        Some((tag, value)) => (tag.trim().to_uppercase(), value.trim()),
+        None => return Ok(None),
+    };
+
+    match tag.as_str() {
+        "START-OF-LOG" => {
+            *header = Header::default();
+            Ok(None)
+        }
+        "CALLSIGN" => {
+            header.callsign = Some(value.to_uppercase()).filter(|v| !v.is_empty());
+            Ok(None)
+        }
+        "CONTEST" => {
+            header.contest = Some(value.to_string()).filter(|v| !v.is_empty());
+            Ok(None)
+        }
+        "OPERATORS" => {
+            header.operators = Some(value.to_uppercase()).filter(|v| !v.is_empty());
+            Ok(None)
+        }
+        "QSO" => parse_qso(value, header).map(Some),
+        _ => Ok(None),
+    }
+}
+
+fn parse_qso(value: &str, header: &Header) -> Result<ContactInfo, CabrilloError> {
+    let tokens: Vec<&str> = value.split_whitespace().collect();
+    if tokens.len() < 6 {
+        return Err(CabrilloError::MalformedLine(value.to_string()));
+    }
+
+    let (frequency, is_exact) = parse_frequency(tokens[0])?;
+    let mode = match tokens[1].to_uppercase().as_str() {
+        "PH" => "SSB".to_string(),
+        "RY" => "RTTY".to_string(),
+        "DG" => "DIGI".to_string(),
+        mode => mode.to_string(),
+    };
+    let timestamp =
+        NaiveDateTime::parse_from_str(&format!("{} {}", tokens[2], tokens[3]), "%Y-%m-%d %H%M")
+            .map_err(|_| {
+                CabrilloError::FieldParsing(
+                    "date/time".to_string(),
+                    format!("{} {}", tokens[2], tokens[3]),
+                )
+            })?
+            .and_utc();
+
+    // Both stations send the same number of exchange fields, optionally followed by the
+    // transmitter id used in multi-transmitter categories.
+    let fields = &tokens[4..];
+    let fields = if fields.len() % 2 == 1 {
+        &fields[..fields.len() - 1]
+    } else {
+        fields
+    };
+    if fields.len() < 2 {
+        return Err(CabrilloError::MalformedLine(value.to_string()));
+    }
+    let exchange_len = fields.len() / 2 - 1;
+    let my_call = fields[0].to_uppercase();
+    let sent = &fields[1..1 + exchange_len];
+    let call = fields[1 + exchange_len].to_uppercase();
+    let received = &fields[2 + exchange_len..];
+
+    let (sent_rst, received_rst, received) = match (sent.first(), received.first()) {
+        (Some(s), Some(r)) if is_rst(s) && is_rst(r) => {
+            (Some(s.to_string()), Some(r.to_string()), &received[1..])
+        }
+        _ => (None, None, received),
+    };
+
+    let mut contact_info = ContactInfo {
+        id: None,
+        source: None,
+        logger: Some("Cabrillo".to_string()),
+        contest_name: header.contest.clone(),
+        timestamp: Some(timestamp),
+        my_call: Some(my_call).or(header.callsign.clone()),
+        operator: header.operators.clone(),
+        station_name: None,
+        call,
+        band: frequency.map(band_from_frequency).unwrap_or_default(),
+        mode: Some(mode),
+        tx_frequency: frequency.filter(|_| is_exact),
+        rx_frequency: None,
+        country_prefix: None,
+        wpx_prefix: None,
+        grid: None,
+        location: None,
+        sent_rst,
+        received_rst,
+        serial_number: None,
+        exchange1: received.first().map(|v| v.to_string()),
+        exchange2: received.get(1).map(|v| v.to_string()),
+        exchange3: received.get(2).map(|v| v.to_string()),
+        duplicate: false,
+        long_path: false,
+        points: None,
+    };
+    contact_info.id = contact_info.synthetic_id();
+
+    Ok(contact_info)
+}
+
+fn parse_frequency(value: &str) -> Result<(Option<u64>, bool), CabrilloError> {
+    let error = || CabrilloError::FieldParsing("freq".to_string(), value.to_string());
+
+    if let Some(ghz) = value.to_uppercase().strip_suffix('G') {
+        let ghz: f64 = ghz.parse().map_err(|_| error())?;
+        return Ok((Some((ghz * 1_000_000_000.0).round() as u64), false));
+    }
+
+    let value: u64 = value.parse().map_err(|_| error())?;
+    match value {
+        0 => Ok((None, false)),
+        // VHF and up are logged with the band designator in MHz rather than the frequency.
+        v if v < 1_800 => Ok((Some(v * 1_000_000), false)),
+        v => Ok((Some(v * 1_000), true)),
+    }
+}
+
+fn is_rst(value: &str) -> bool {
+    (2..=3).contains(&value.len()) && value.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cabrillo::{parse_line, Header};
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_parse_line_qso() {
+        let mut header = Header::default();
+        parse_line("START-OF-LOG: 3.0", &mut header).unwrap();
+        parse_line("CALLSIGN: IS0GVH", &mut header).unwrap();
+        parse_line("CONTEST: CQ-WW-CW", &mut header).unwrap();
+
+        let actual = parse_line(
+            "QSO:  7005 CW 2024-11-23 0711 IS0GVH        599 15     W1AW          599 05",
+            &mut header,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(actual.call, "W1AW");
+        assert_eq!(actual.band, "40");
+        assert_eq!(actual.mode, Some("CW".to_string()));
+        assert_eq!(actual.tx_frequency, Some(7_005_000));
+        assert_eq!(actual.my_call, Some("IS0GVH".to_string()));
+        assert_eq!(actual.contest_name, Some("CQ-WW-CW".to_string()));
+        assert_eq!(actual.sent_rst, Some("599".to_string()));
+        assert_eq!(actual.received_rst, Some("599".to_string()));
+        assert_eq!(actual.exchange1, Some("05".to_string()));
+        assert_eq!(
+            actual.timestamp,
+            Some(Utc.with_ymd_and_hms(2024, 11, 23, 7, 11, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_parse_line_qso_vhf_with_transmitter() {
+        let mut header = Header::default();
+
+        let actual = parse_line(
+            "QSO: 144 PH 2024-06-08 1412 IS0GVH 59 001 JM49NA I0ABC 59 017 JN61FW 1",
+            &mut header,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert_eq!(actual.call, "I0ABC");
+        assert_eq!(actual.band, "2");
+        assert_eq!(actual.mode, Some("SSB".to_string()));
+        assert_eq!(actual.tx_frequency, None);
+        assert_eq!(actual.exchange1, Some("017".to_string()));
+        assert_eq!(actual.exchange2, Some("JN61FW".to_string()));
+    }
+
+    #[test]
+    fn test_parse_line_header_and_malformed() {
+        let mut header = Header::default();
+
+        assert_eq!(parse_line("CREATED-BY: Test", &mut header).unwrap(), None);
+        assert!(parse_line("QSO: 7005 CW", &mut header).is_err());
+    }
+}
@@ -0,0 +1,310 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::callbook::{CallbookError, CallbookRecord};
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+#[derive(Debug)]
+pub enum CacheError {
+    IO(std::io::Error),
+    Serialization(serde_json::Error),
+}
+
+impl Display for CacheError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheError::IO(e) => {
+                write!(f, "IO error: {}", e)
+            }
+            CacheError::Serialization(e) => {
+                write!(f, "Serialization error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for CacheError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl From<serde_json::Error> for CacheError {
+    fn from(value: serde_json::Error) -> Self {
+        Self::Serialization(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct CacheEntry {
+    record: CallbookRecord,
+    fetched_at: DateTime<Utc>,
+}
+
+pub type LookupResult = Result<CallbookRecord, Arc<CallbookError>>;
+
+#[derive(Debug, Default)]
+pub struct CallsignCache {
+    path: Option<PathBuf>,
+    max_age: TimeDelta,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    in_flight: Mutex<HashMap<String, Arc<OnceCell<LookupResult>>>>,
+    dirty: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CallsignCache {
+    pub fn new(path: Option<PathBuf>, max_age: Duration) -> Self {
+        let entries = match &path {
+            Some(path) if path.exists() => match load(path) {
+                Ok(entries) => {
+                    log::info!(
+                        "Loaded {} callsigns from cache {}",
+                        entries.len(),
+                        path.display()
+                    );
+                    entries
+                }
+                Err(e) => {
+                    log::warn!("Unable to load cache {}: {}", path.display(), e);
+                    HashMap::new()
+                }
+            },
+            _ => HashMap::new(),
+        };
+
+        Self {
+            path,
+            max_age: TimeDelta::from_std(max_age).unwrap_or(TimeDelta::MAX),
+            entries: Mutex::new(entries),
+            ..Default::default()
+        }
+    }
+
+    pub fn get(&self, call: &str, now: DateTime<Utc>) -> Option<CallbookRecord> {
+        let mut entries = self.entries.lock().unwrap();
+
+        let record = match entries.get(call) {
+            Some(entry) if now - entry.fetched_at <= self.max_age => Some(entry.record.clone()),
+            Some(_) => {
+                entries.remove(call);
+                self.dirty.store(true, Ordering::Relaxed);
+                None
+            }
+            None => None,
+        };
+
+        match record {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+
+        record
+    }
+
+    pub async fn get_or_lookup<F>(&self, call: &str, now: DateTime<Utc>, lookup: F) -> LookupResult
+    where
+        F: Future<Output = Result<CallbookRecord, CallbookError>>,
+    {
+        if let Some(record) = self.get(call, now) {
+            return Ok(record);
+        }
+
+        let cell = self
+            .in_flight
+            .lock()
+            .unwrap()
+            .entry(call.to_string())
+            .or_default()
+            .clone();
+
+        let result = cell
+            .get_or_init(|| async {
+                let result = lookup.await.map_err(Arc::new);
+                if let Ok(record) = &result {
+                    self.insert(call, record.clone(), now);
+                }
+                result
+            })
+            .await
+            .clone();
+
+        let mut in_flight = self.in_flight.lock().unwrap();
+        if in_flight
+            .get(call)
+            .is_some_and(|in_flight_cell| Arc::ptr_eq(in_flight_cell, &cell))
+        {
+            in_flight.remove(call);
+        }
+
+        result
+    }
+
+    pub fn insert(&self, call: &str, record: CallbookRecord, now: DateTime<Utc>) {
+        self.entries.lock().unwrap().insert(
+            call.to_string(),
+            CacheEntry {
+                record,
+                fetched_at: now,
+            },
+        );
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    pub fn save(&self) -> Result<(), CacheError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+
+        let payload = {
+            let entries = self.entries.lock().unwrap();
+            serde_json::to_vec(&*entries)?
+        };
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        if let Err(e) =
+            std::fs::write(&temp_path, payload).and_then(|_| std::fs::rename(&temp_path, path))
+        {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(e.into());
+        }
+
+        log::debug!("Cache saved to {}", path.display());
+        Ok(())
+    }
+
+    pub fn hits(&self) -> u64 {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    pub fn misses(&self) -> u64 {
+        self.misses.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+fn load(path: &Path) -> Result<HashMap<String, CacheEntry>, CacheError> {
+    let payload = std::fs::read(path)?;
+    Ok(serde_json::from_slice(&payload)?)
+}
+
+pub async fn run_cache_persister(callsign_cache: Arc<CallsignCache>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if let Err(e) = callsign_cache.save() {
+            log::warn!("Unable to save cache: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::CallsignCache;
+    use crate::callbook::CallbookRecord;
+    use chrono::{TimeDelta, Utc};
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::time::Duration;
+
+    fn record() -> CallbookRecord {
+        CallbookRecord {
+            call: "IS0GVH".to_string(),
+            latitude: Some(39.123456),
+            longitude: Some(9.654321),
+            grid: Some("JM49".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_get_max_age() {
+        let cache = CallsignCache::new(None, Duration::from_secs(3600));
+        let now = Utc::now();
+
+        assert_eq!(cache.get("IS0GVH", now), None);
+
+        cache.insert("IS0GVH", record(), now);
+        assert_eq!(
+            cache.get("IS0GVH", now + TimeDelta::minutes(30)),
+            Some(record())
+        );
+        assert_eq!(cache.get("IS0GVH", now + TimeDelta::minutes(90)), None);
+        assert_eq!(cache.len(), 0);
+
+        assert_eq!(cache.hits(), 1);
+        assert_eq!(cache.misses(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_get_or_lookup_coalescing() {
+        let cache = CallsignCache::new(None, Duration::from_secs(3600));
+        let lookups = AtomicU32::new(0);
+        let now = Utc::now();
+
+        let lookup = || async {
+            lookups.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+            Ok(record())
+        };
+
+        let (first, second) = tokio::join!(
+            cache.get_or_lookup("IS0GVH", now, lookup()),
+            cache.get_or_lookup("IS0GVH", now, lookup())
+        );
+
+        assert_eq!(first.unwrap(), record());
+        assert_eq!(second.unwrap(), record());
+        assert_eq!(lookups.load(Ordering::Relaxed), 1);
+
+        let third = cache.get_or_lookup("IS0GVH", now, lookup()).await;
+        assert_eq!(third.unwrap(), record());
+        assert_eq!(lookups.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("live-qso-map-cache-{}.json", std::process::id()));
+        let now = Utc::now();
+
+        let cache = CallsignCache::new(Some(path.clone()), Duration::from_secs(3600));
+        cache.insert("IS0GVH", record(), now);
+        cache.save().unwrap();
+
+        let cache = CallsignCache::new(Some(path.clone()), Duration::from_secs(3600));
+        assert_eq!(cache.get("IS0GVH", now), Some(record()));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,237 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::hamqth::HamQTHError;
+use crate::qrzcom::QRZComError;
+use async_trait::async_trait;
+use clap::ValueEnum;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CallbookKind {
+    Qrzcom,
+    Hamqth,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CallbookRecord {
+    pub call: String,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    #[serde(default)]
+    pub grid: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum CallbookError {
+    QRZCom(QRZComError),
+    HamQTH(HamQTHError),
+    Unavailable(String),
+    NoProvider,
+}
+
+impl Display for CallbookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallbookError::QRZCom(e) => {
+                write!(f, "QRZ.com error: {}", e)
+            }
+            CallbookError::HamQTH(e) => {
+                write!(f, "HamQTH error: {}", e)
+            }
+            CallbookError::Unavailable(name) => {
+                write!(f, "{} temporarily unavailable", name)
+            }
+            CallbookError::NoProvider => {
+                write!(f, "No callbook provider available")
+            }
+        }
+    }
+}
+
+impl CallbookError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            CallbookError::QRZCom(e) => e.is_transient(),
+            CallbookError::HamQTH(e) => e.is_transient(),
+            CallbookError::Unavailable(_) | CallbookError::NoProvider => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        match self {
+            CallbookError::QRZCom(e) => e.is_not_found(),
+            CallbookError::HamQTH(e) => e.is_not_found(),
+            CallbookError::Unavailable(_) | CallbookError::NoProvider => false,
+        }
+    }
+
+    fn priority(&self) -> u8 {
+        match (self.is_transient(), self.is_not_found()) {
+            (true, _) => 2,
+            (false, true) => 1,
+            (false, false) => 0,
+        }
+    }
+}
+
+impl From<QRZComError> for CallbookError {
+    fn from(value: QRZComError) -> Self {
+        Self::QRZCom(value)
+    }
+}
+
+impl From<HamQTHError> for CallbookError {
+    fn from(value: HamQTHError) -> Self {
+        Self::HamQTH(value)
+    }
+}
+
+pub fn is_transient_request_error(error: &reqwest::Error) -> bool {
+    error.is_timeout()
+        || error.is_connect()
+        || error.is_body()
+        || error.status().is_some_and(|status| {
+            status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+        })
+}
+
+#[async_trait]
+pub trait CallbookProvider: Send + Sync {
+    fn name(&self) -> &str;
+
+    async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError>;
+}
+
+#[derive(Default)]
+pub struct CallbookChain {
+    providers: Vec<Box<dyn CallbookProvider>>,
+}
+
+impl CallbookChain {
+    pub fn new(providers: Vec<Box<dyn CallbookProvider>>) -> Self {
+        Self { providers }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.providers.is_empty()
+    }
+}
+
+#[async_trait]
+impl CallbookProvider for CallbookChain {
+    fn name(&self) -> &str {
+        "chain"
+    }
+
+    async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+        let mut last_error = CallbookError::NoProvider;
+
+        for provider in &self.providers {
+            match provider.lookup(callsign).await {
+                Ok(record) => {
+                    log::debug!("{} found {}", provider.name(), callsign);
+                    return Ok(record);
+                }
+                Err(e) => {
+                    log::info!("{} lookup of {} failed: {}", provider.name(), callsign, e);
+                    if e.priority() >= last_error.priority() {
+                        last_error = e;
+                    }
+                }
+            }
+        }
+
+        Err(last_error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::callbook::{CallbookChain, CallbookError, CallbookProvider, CallbookRecord};
+    use crate::qrzcom::QRZComError;
+    use async_trait::async_trait;
+
+    struct StaticProvider(Option<CallbookRecord>);
+
+    struct TransientProvider;
+
+    #[async_trait]
+    impl CallbookProvider for TransientProvider {
+        fn name(&self) -> &str {
+            "transient"
+        }
+
+        async fn lookup(&self, _callsign: &str) -> Result<CallbookRecord, CallbookError> {
+            Err(CallbookError::QRZCom(QRZComError::Parsing(
+                serde_xml_rs::from_str::<String>("<").unwrap_err(),
+            )))
+        }
+    }
+
+    #[async_trait]
+    impl CallbookProvider for StaticProvider {
+        fn name(&self) -> &str {
+            "static"
+        }
+
+        async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+            self.0
+                .clone()
+                .ok_or(CallbookError::QRZCom(QRZComError::ApiError(format!(
+                    "Not found: {}",
+                    callsign
+                ))))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chain_lookup() {
+        let record = CallbookRecord {
+            call: "IS0GVH".to_string(),
+            latitude: Some(39.123456),
+            longitude: Some(9.654321),
+            grid: Some("JM49".to_string()),
+        };
+
+        let chain = CallbookChain::new(vec![
+            Box::new(StaticProvider(None)),
+            Box::new(StaticProvider(Some(record.clone()))),
+        ]);
+        assert_eq!(chain.lookup("IS0GVH").await.unwrap(), record);
+
+        let chain = CallbookChain::new(vec![Box::new(StaticProvider(None))]);
+        assert!(matches!(
+            chain.lookup("IS0GVH").await,
+            Err(CallbookError::QRZCom(_))
+        ));
+
+        let chain = CallbookChain::new(vec![
+            Box::new(TransientProvider),
+            Box::new(StaticProvider(None)),
+        ]);
+        let error = chain.lookup("IS0GVH").await.unwrap_err();
+        assert!(error.is_transient());
+
+        let chain = CallbookChain::default();
+        assert!(matches!(
+            chain.lookup("IS0GVH").await,
+            Err(CallbookError::NoProvider)
+        ));
+    }
+}
@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use std::fmt::{Display, Formatter};
+
+const MODIFIERS: [&str; 12] = [
+    "P", "M", "MM", "AM", "A", "QRP", "QRPP", "LH", "B", "R", "T", "J",
+];
+const PREFIX_MODIFIERS: [&str; 5] = ["MM", "AM", "QRP", "QRPP", "LH"];
+const NOT_LOCATABLE: [&str; 2] = ["MM", "AM"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Callsign {
+    pub prefix: Option<String>,
+    pub base: String,
+    pub suffix: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallsignLocation {
+    Base,
+    Foreign(String),
+    NotLocatable,
+}
+
+impl Callsign {
+    pub fn parse(call: &str) -> Self {
+        let call = call.trim().to_uppercase();
+        let parts: Vec<&str> = call.split('/').filter(|part| !part.is_empty()).collect();
+
+        match parts.as_slice() {
+            [] => Self::new(None, "", None),
+            [base] => Self::new(None, base, None),
+            [first, second] if is_modifier(second) => Self::new(None, first, Some(second)),
+            [first, second] if is_prefix_modifier(first) => Self::new(None, second, Some(first)),
+            [first, second] if second.len() > first.len() => Self::new(Some(first), second, None),
+            [first, second] => Self::new(None, first, Some(second)),
+            [first, second, .., last] if second.len() > first.len() => {
+                Self::new(Some(first), second, Some(last))
+            }
+            [first, second, .., last] if !is_modifier(second) => {
+                Self::new(Some(second), first, Some(last))
+            }
+            [first, .., last] => Self::new(None, first, Some(last)),
+        }
+    }
+
+    fn new(prefix: Option<&str>, base: &str, suffix: Option<&str>) -> Self {
+        Self {
+            prefix: prefix.map(str::to_string),
+            base: base.to_string(),
+            suffix: suffix.map(str::to_string),
+        }
+    }
+
+    pub fn location(&self) -> CallsignLocation {
+        if let Some(suffix) = &self.suffix {
+            if NOT_LOCATABLE.contains(&suffix.as_str()) {
+                return CallsignLocation::NotLocatable;
+            }
+        }
+
+        if let Some(prefix) = &self.prefix {
+            if !is_prefix_modifier(prefix) {
+                return CallsignLocation::Foreign(prefix.clone());
+            }
+        }
+
+        match &self.suffix {
+            Some(suffix) if !is_modifier(suffix) && !is_call_area(suffix) => {
+                CallsignLocation::Foreign(suffix.clone())
+            }
+            _ => CallsignLocation::Base,
+        }
+    }
+}
+
+impl Display for Callsign {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(prefix) = &self.prefix {
+            write!(f, "{}/", prefix)?;
+        }
+        write!(f, "{}", self.base)?;
+        if let Some(suffix) = &self.suffix {
+            write!(f, "/{}", suffix)?;
+        }
+        Ok(())
+    }
+}
+
+fn is_modifier(part: &str) -> bool {
+    MODIFIERS.contains(&part) || is_call_area(part)
+}
+
+fn is_prefix_modifier(part: &str) -> bool {
+    PREFIX_MODIFIERS.contains(&part)
+}
+
+fn is_call_area(part: &str) -> bool {
+    part.len() == 1 && part.chars().all(|c| c.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::callsign::{Callsign, CallsignLocation};
+
+    fn callsign(prefix: Option<&str>, base: &str, suffix: Option<&str>) -> Callsign {
+        Callsign {
+            prefix: prefix.map(str::to_string),
+            base: base.to_string(),
+            suffix: suffix.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Callsign::parse("is0gvh"), callsign(None, "IS0GVH", None));
+        assert_eq!(
+            Callsign::parse("K1ABC/P"),
+            callsign(None, "K1ABC", Some("P"))
+        );
+        assert_eq!(
+            Callsign::parse("EA8/IS0GVH/P"),
+            callsign(Some("EA8"), "IS0GVH", Some("P"))
+        );
+        assert_eq!(
+            Callsign::parse("VP2E/W1XYZ"),
+            callsign(Some("VP2E"), "W1XYZ", None)
+        );
+        assert_eq!(
+            Callsign::parse("IS0GVH/EA8"),
+            callsign(None, "IS0GVH", Some("EA8"))
+        );
+        assert_eq!(
+            Callsign::parse("W1ABC/4"),
+            callsign(None, "W1ABC", Some("4"))
+        );
+        assert_eq!(
+            Callsign::parse("QRP/I1ABC"),
+            callsign(None, "I1ABC", Some("QRP"))
+        );
+        assert_eq!(
+            Callsign::parse("IS0GVH/EA8/P"),
+            callsign(Some("EA8"), "IS0GVH", Some("P"))
+        );
+        assert_eq!(
+            Callsign::parse("M/DL1ABC"),
+            callsign(Some("M"), "DL1ABC", None)
+        );
+        assert_eq!(
+            Callsign::parse("F/G4ABC/P"),
+            callsign(Some("F"), "G4ABC", Some("P"))
+        );
+        assert_eq!(Callsign::parse("EA8/IS0GVH/P").to_string(), "EA8/IS0GVH/P");
+    }
+
+    #[test]
+    fn test_location() {
+        assert_eq!(Callsign::parse("IS0GVH").location(), CallsignLocation::Base);
+        assert_eq!(
+            Callsign::parse("K1ABC/P").location(),
+            CallsignLocation::Base
+        );
+        assert_eq!(
+            Callsign::parse("K1ABC/QRP").location(),
+            CallsignLocation::Base
+        );
+        assert_eq!(
+            Callsign::parse("W1ABC/4").location(),
+            CallsignLocation::Base
+        );
+        assert_eq!(
+            Callsign::parse("EA8/IS0GVH/P").location(),
+            CallsignLocation::Foreign("EA8".to_string())
+        );
+        assert_eq!(
+            Callsign::parse("VP2E/W1XYZ").location(),
+            CallsignLocation::Foreign("VP2E".to_string())
+        );
+        assert_eq!(
+            Callsign::parse("K1ABC/KH6").location(),
+            CallsignLocation::Foreign("KH6".to_string())
+        );
+        assert_eq!(
+            Callsign::parse("M/DL1ABC").location(),
+            CallsignLocation::Foreign("M".to_string())
+        );
+        assert_eq!(
+            Callsign::parse("F/G4ABC/P").location(),
+            CallsignLocation::Foreign("F".to_string())
+        );
+        assert_eq!(
+            Callsign::parse("QRP/I1ABC/P").location(),
+            CallsignLocation::Base
+        );
+        assert_eq!(
+            Callsign::parse("K1ABC/MM").location(),
+            CallsignLocation::NotLocatable
+        );
+        assert_eq!(
+            Callsign::parse("K1ABC/AM").location(),
+            CallsignLocation::NotLocatable
+        );
+    }
+}
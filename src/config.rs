@@ -14,8 +14,17 @@
  *
  */
 
+use crate::callbook::CallbookKind;
+use crate::dedup::DupePolicy;
+use crate::enricher::UnknownLocationPolicy;
+use crate::receiver::{ReceiverProtocol, Source, MAX_DATAGRAM_SIZE};
+use crate::throttle::MIN_RATE_LIMIT;
+use crate::watcher::LogFormat;
 use clap::{ArgAction, Parser};
+use ipnet::IpNet;
 use log::Level;
+use std::net::IpAddr;
+use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, author, about, long_about = None)]
@@ -50,13 +59,23 @@ pub struct Config {
     )]
     pub http_port: u16,
 
+    #[arg(
+        short = 'T',
+        long,
+        env = "LIVE_QSO_MAP_API_TOKEN",
+        action = ArgAction::Set,
+        help = "API token",
+        long_help = "Bearer token required by the authenticated HTTP APIs, which are disabled when not set"
+    )]
+    pub api_token: Option<String>,
+
     #[arg(
         short = 'I',
         long,
         action = ArgAction::Set,
         default_value = "::",
-        help = "QARTest binding",
-        long_help = "Binding address for the QARTest UDP socket receiver"
+        help = "Logger binding",
+        long_help = "Binding address for the logger UDP socket receiver"
     )]
     pub bind_host: String,
 
@@ -65,30 +84,337 @@ pub struct Config {
         long,
         action = ArgAction::Set,
         default_value = "12060",
-        help = "QARTest port",
-        long_help = "Port for the QARTest UDP socket receiver"
+        help = "Logger port",
+        long_help = "Port for the logger UDP socket receiver"
     )]
     pub bind_port: u16,
 
+    #[arg(
+        short = 'R',
+        long,
+        action = ArgAction::Set,
+        default_value = "xml",
+        help = "Logger protocol",
+        long_help = "Protocol of the UDP socket receiver: QARTest/N1MM+ XML, raw ADIF records or WSJT-X"
+    )]
+    pub bind_protocol: ReceiverProtocol,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Logger multicast group",
+        long_help = "IPv4 or IPv6 multicast group joined by the UDP socket receiver"
+    )]
+    pub bind_multicast_group: Option<IpAddr>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Logger multicast interface",
+        long_help = "Interface used to join the multicast group: local address for IPv4, interface name or index for IPv6"
+    )]
+    pub bind_multicast_interface: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "Logger address reuse",
+        long_help = "Enable SO_REUSEADDR and SO_REUSEPORT on the UDP socket receiver to share the port with other listeners"
+    )]
+    pub bind_reuse: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 32768,
+        value_parser = clap::value_parser!(u32).range(512..=MAX_DATAGRAM_SIZE as i64),
+        help = "Maximum datagram size",
+        long_help = "Size in bytes of the largest datagram accepted from the loggers, larger datagrams are \
+discarded as truncated"
+    )]
+    pub max_datagram_size: u32,
+
+    #[arg(
+        short = 'S',
+        long = "source",
+        action = ArgAction::Append,
+        help = "Logger source",
+        long_help = "UDP socket receiver in the form protocol://host:port[?group=...&interface=...&reuse=true][#label], \
+can be repeated. When set, it replaces the receiver configured by --bind-host, --bind-port and --bind-protocol"
+    )]
+    pub sources: Vec<Source>,
+
+    #[arg(
+        short = 'A',
+        long = "allow",
+        action = ArgAction::Append,
+        help = "Allowed logger network",
+        long_help = "Network in CIDR notation allowed to send datagrams to the UDP socket receivers, can be repeated. \
+When not set, datagrams are accepted from any address"
+    )]
+    pub allow_list: Vec<IpNet>,
+
+    #[arg(
+        long,
+        env = "LIVE_QSO_MAP_HMAC_SECRET",
+        action = ArgAction::Set,
+        help = "Datagram signing secret",
+        long_help = "Shared secret for HMAC-SHA256 signed datagrams. When set, the UDP socket receivers only accept \
+datagrams made of the hex encoded signature of the payload, a newline and the payload itself"
+    )]
+    pub hmac_secret: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value = "show",
+        help = "Contest dupes policy",
+        long_help = "What to do with contacts flagged as contest dupes by the logger: hide them from the map or show \
+them with the duplicate flag set"
+    )]
+    pub dupe_policy: DupePolicy,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 120,
+        help = "Deduplication window in seconds",
+        long_help = "Time window, in seconds, in which a contact without logger id and with the same call, band and \
+mode is considered a resend of a previous one. Contacts with a logger id are considered resends when the same id \
+was seen in the last 24 hours, regardless of this window"
+    )]
+    pub dedup_window: u64,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "TCP ingest binding",
+        long_help = "Address and port of the TCP listener accepting newline-delimited JSON contacts, disabled when not set"
+    )]
+    pub ingest_tcp: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Unix socket ingest path",
+        long_help = "Path of the Unix domain socket accepting newline-delimited JSON contacts, disabled when not set"
+    )]
+    pub ingest_unix: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Watched log file",
+        long_help = "ADIF or Cabrillo log file to watch for new QSOs, disabled when not set"
+    )]
+    pub watch_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Watched log file format",
+        long_help = "Format of the watched log file, guessed from the file extension when not set"
+    )]
+    pub watch_format: Option<LogFormat>,
+
+    #[arg(
+        short = 'C',
+        long = "callbook",
+        action = ArgAction::Append,
+        value_delimiter = ',',
+        default_value = "qrzcom,hamqth",
+        help = "Callbook providers",
+        long_help = "Callbook providers queried in order until one of them finds the callsign, providers without \
+credentials are skipped"
+    )]
+    pub callbooks: Vec<CallbookKind>,
+
     #[arg(
         short = 'u',
         long,
         action = ArgAction::Set,
-        required = true,
+        requires = "qrzcom_password",
         help = "QRZ.com User",
         long_help = "Username for the QRZ.com XML APIs"
     )]
-    pub qrzcom_user: String,
+    pub qrzcom_user: Option<String>,
 
     #[arg(
         short = 'p',
         long,
         action = ArgAction::Set,
-        required = true,
+        requires = "qrzcom_user",
         help = "QRZ.com Password",
         long_help = "Password for the QRZ.com XML APIs"
     )]
-    pub qrzcom_password: String,
+    pub qrzcom_password: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        requires = "hamqth_password",
+        help = "HamQTH User",
+        long_help = "Username for the HamQTH.com XML APIs"
+    )]
+    pub hamqth_user: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        requires = "hamqth_user",
+        help = "HamQTH Password",
+        long_help = "Password for the HamQTH.com XML APIs"
+    )]
+    pub hamqth_password: Option<String>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Country file",
+        long_help = "Path of a cty.dat or cty.csv (Big CTY) country file used to resolve the DXCC entity of every \
+contact, and its centroid when the callbook has no location or no callbook is configured"
+    )]
+    pub cty_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Location overrides file",
+        long_help = "Path of a TOML or CSV file of callsigns or regular expressions mapped to coordinates or a grid \
+locator, used instead of the callbook; the file is reloaded when changed and created when overrides are edited \
+through the API"
+    )]
+    pub overrides_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value = "unlocated",
+        help = "Unknown location policy",
+        long_help = "What to do with contacts whose location cannot be resolved: drop them, hold them and retry \
+the lookup later, or send them to the map as unlocated"
+    )]
+    pub unknown_location: UnknownLocationPolicy,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 60,
+        value_parser = clap::value_parser!(u64).range(1..),
+        help = "Held contacts retry interval in seconds",
+        long_help = "Interval, in seconds, between lookup retries of contacts held because of an unknown location"
+    )]
+    pub hold_interval: u64,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 10,
+        help = "Held contacts max attempts",
+        long_help = "Number of lookup retries of a held contact before giving up"
+    )]
+    pub hold_attempts: u32,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 4,
+        value_parser = clap::value_parser!(u16).range(1..),
+        help = "Concurrent enrichment workers",
+        long_help = "Maximum number of contacts enriched concurrently, lookups of the same callsign are always \
+coalesced into a single callbook request"
+    )]
+    pub enricher_workers: u16,
+
+    #[arg(
+        long,
+        action = ArgAction::SetTrue,
+        help = "Preserve logger order",
+        long_help = "Publish enriched contacts to the map in the same order they were received from the loggers, \
+instead of as soon as each lookup completes"
+    )]
+    pub preserve_order: bool,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 3,
+        help = "Callbook lookup retries",
+        long_help = "Number of times a callbook lookup is retried after a transient failure (timeout, connection \
+error, server error) before the contact is moved to the dead-letter list"
+    )]
+    pub lookup_retries: u32,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 1,
+        help = "Callbook lookup retry delay (seconds)",
+        long_help = "Delay before the first retry of a failed callbook lookup, doubled at each further retry up to \
+60 seconds"
+    )]
+    pub lookup_retry_delay: u64,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 2.0,
+        value_parser = parse_rate_limit,
+        help = "Callbook requests per second",
+        long_help = "Maximum sustained rate of requests sent to each callbook provider, excess lookups are delayed; \
+0 disables the rate limit, otherwise it must be at least 0.001"
+    )]
+    pub callbook_rate_limit: f64,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Callbook requests burst",
+        long_help = "Number of requests that can be sent to each callbook provider at once before the rate limit \
+applies"
+    )]
+    pub callbook_burst: u32,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u32).range(1..),
+        help = "Callbook failures before circuit opens",
+        long_help = "Number of consecutive transient failures after which a callbook provider is considered down \
+and contacts are located from the cache or the country file without querying it"
+    )]
+    pub callbook_failure_threshold: u32,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 30,
+        help = "Callbook probe interval (seconds)",
+        long_help = "Time to wait before probing a callbook provider considered down with a single lookup, the \
+provider is used again as soon as a probe succeeds"
+    )]
+    pub callbook_probe_interval: u64,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        help = "Callsign cache file",
+        long_help = "Path of the JSON file where callsign lookups are persisted across restarts, the cache is kept \
+only in memory when not set"
+    )]
+    pub cache_file: Option<PathBuf>,
+
+    #[arg(
+        long,
+        action = ArgAction::Set,
+        default_value_t = 604800,
+        help = "Callsign cache max age in seconds",
+        long_help = "Maximum age, in seconds, of a cached callsign lookup before it is requested again to the callbook"
+    )]
+    pub cache_max_age: u64,
 
     #[arg(
         short = 'a',
@@ -110,3 +436,16 @@ pub struct Config {
     )]
     pub home_longitude: f64,
 }
+
+fn parse_rate_limit(value: &str) -> Result<f64, String> {
+    let rate_limit: f64 = value
+        .parse()
+        .map_err(|e: std::num::ParseFloatError| e.to_string())?;
+    match rate_limit == 0.0 || (MIN_RATE_LIMIT..=f64::MAX).contains(&rate_limit) {
+        true => Ok(rate_limit),
+        false => Err(format!(
+            "must be 0 or a finite value of at least {}",
+            MIN_RATE_LIMIT
+        )),
+    }
+}
@@ -0,0 +1,135 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::receiver::ContactInfo;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+const DEFAULT_CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DeadLetter {
+    pub id: u64,
+    pub contact: ContactInfo,
+    pub error: String,
+    pub failed_at: DateTime<Utc>,
+}
+
+pub struct DeadLetters {
+    capacity: usize,
+    entries: Mutex<VecDeque<DeadLetter>>,
+    next_id: AtomicU64,
+}
+
+impl Default for DeadLetters {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl DeadLetters {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            entries: Mutex::new(VecDeque::new()),
+            next_id: AtomicU64::new(1),
+        }
+    }
+
+    pub fn push(&self, contact: ContactInfo, error: String, failed_at: DateTime<Utc>) -> u64 {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut entries = self.entries.lock().unwrap();
+
+        if let Some(contact_id) = &contact.id {
+            entries.retain(|entry| entry.contact.id.as_ref() != Some(contact_id));
+        }
+        if entries.len() >= self.capacity {
+            if let Some(evicted) = entries.pop_front() {
+                log::warn!("Dead-letter list full, discarding {}", evicted.contact);
+            }
+        }
+
+        entries.push_back(DeadLetter {
+            id,
+            contact,
+            error,
+            failed_at,
+        });
+        id
+    }
+
+    pub fn list(&self) -> Vec<DeadLetter> {
+        self.entries.lock().unwrap().iter().cloned().collect()
+    }
+
+    pub fn take(&self, id: u64) -> Option<DeadLetter> {
+        let mut entries = self.entries.lock().unwrap();
+        let index = entries.iter().position(|entry| entry.id == id)?;
+        entries.remove(index)
+    }
+
+    pub fn remove_contact(&self, contact_id: &str) {
+        self.entries
+            .lock()
+            .unwrap()
+            .retain(|entry| entry.contact.id.as_deref() != Some(contact_id));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deadletter::DeadLetters;
+    use crate::receiver::ContactInfo;
+    use chrono::Utc;
+
+    fn contact_info(id: Option<&str>, call: &str) -> ContactInfo {
+        serde_json::from_value(serde_json::json!({"id": id, "call": call, "band": "20"})).unwrap()
+    }
+
+    #[test]
+    fn test_dead_letters() {
+        let dead_letters = DeadLetters::new(2);
+        let now = Utc::now();
+
+        let first = dead_letters.push(contact_info(Some("1"), "IS0XXX"), "Not found".into(), now);
+        dead_letters.push(contact_info(Some("1"), "IS0XXY"), "Not found".into(), now);
+        assert_eq!(dead_letters.len(), 1);
+        assert!(dead_letters.take(first).is_none());
+
+        dead_letters.push(contact_info(None, "IS0AAA"), "Not found".into(), now);
+        let last = dead_letters.push(contact_info(None, "IS0BBB"), "Not found".into(), now);
+        let calls: Vec<String> = dead_letters
+            .list()
+            .into_iter()
+            .map(|entry| entry.contact.call)
+            .collect();
+        assert_eq!(calls, vec!["IS0AAA", "IS0BBB"]);
+
+        assert_eq!(dead_letters.take(last).unwrap().contact.call, "IS0BBB");
+        assert_eq!(dead_letters.len(), 1);
+
+        dead_letters.push(contact_info(Some("2"), "IS0CCC"), "Not found".into(), now);
+        dead_letters.remove_contact("2");
+        assert_eq!(dead_letters.len(), 1);
+    }
+}
@@ -0,0 +1,216 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::receiver::{ContactDeletion, ContactEvent, ContactInfo};
+use async_channel::{Receiver, Sender};
+use clap::ValueEnum;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const ID_RETENTION: Duration = Duration::from_secs(24 * 60 * 60);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum DupePolicy {
+    Hide,
+    Show,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ContactKey {
+    call: String,
+    band: String,
+    mode: Option<String>,
+}
+
+impl From<&ContactInfo> for ContactKey {
+    fn from(value: &ContactInfo) -> Self {
+        Self {
+            call: value.call.clone(),
+            band: value.band.clone(),
+            mode: value.mode.clone(),
+        }
+    }
+}
+
+pub struct Deduplicator {
+    policy: DupePolicy,
+    window: Duration,
+    ids: HashMap<String, Instant>,
+    keys: HashMap<ContactKey, Instant>,
+    last_prune: Option<Instant>,
+}
+
+impl Deduplicator {
+    pub fn new(policy: DupePolicy, window: Duration) -> Self {
+        Self {
+            policy,
+            window,
+            ids: HashMap::new(),
+            keys: HashMap::new(),
+            last_prune: None,
+        }
+    }
+
+    pub fn process(&mut self, contact_event: ContactEvent, now: Instant) -> Option<ContactEvent> {
+        self.prune(now);
+
+        match &contact_event {
+            ContactEvent::Insert(contact_info) => {
+                if self.is_resend(contact_info, now) {
+                    log::debug!("Dropping resent contact: {}", contact_info);
+                    return None;
+                }
+                if contact_info.duplicate && self.policy == DupePolicy::Hide {
+                    log::debug!("Hiding contest dupe: {}", contact_info);
+                    return None;
+                }
+            }
+            ContactEvent::Replace(contact_info) => {
+                if let Some(id) = &contact_info.id {
+                    self.ids.insert(id.clone(), now);
+                }
+                self.keys.insert(ContactKey::from(contact_info), now);
+                if contact_info.duplicate && self.policy == DupePolicy::Hide {
+                    if let Some(id) = &contact_info.id {
+                        log::debug!(
+                            "Replaced contact is a contest dupe, removing: {}",
+                            contact_info
+                        );
+                        return Some(ContactEvent::Delete(ContactDeletion {
+                            id: id.clone(),
+                            call: Some(contact_info.call.clone()),
+                        }));
+                    }
+                    return None;
+                }
+            }
+            ContactEvent::Delete(contact_deletion) => {
+                self.ids.remove(&contact_deletion.id);
+            }
+        }
+
+        Some(contact_event)
+    }
+
+    fn is_resend(&mut self, contact_info: &ContactInfo, now: Instant) -> bool {
+        if let Some(id) = &contact_info.id {
+            return self.ids.insert(id.clone(), now).is_some();
+        }
+
+        match self.keys.insert(ContactKey::from(contact_info), now) {
+            Some(last_seen) => now.duration_since(last_seen) <= self.window,
+            None => false,
+        }
+    }
+
+    fn prune(&mut self, now: Instant) {
+        if self
+            .last_prune
+            .is_some_and(|last_prune| now.duration_since(last_prune) < PRUNE_INTERVAL)
+        {
+            return;
+        }
+        self.last_prune = Some(now);
+
+        let window = self.window;
+        self.ids
+            .retain(|_, last_seen| now.duration_since(*last_seen) <= ID_RETENTION);
+        self.keys
+            .retain(|_, last_seen| now.duration_since(*last_seen) <= window);
+    }
+}
+
+pub async fn run_deduplicator(
+    mut deduplicator: Deduplicator,
+    contact_event_receiver: Receiver<ContactEvent>,
+    contact_event_sender: Sender<ContactEvent>,
+) {
+    while let Ok(contact_event) = contact_event_receiver.recv().await {
+        if let Some(contact_event) = deduplicator.process(contact_event, Instant::now()) {
+            if let Err(e) = contact_event_sender.send(contact_event).await {
+                log::warn!("Failed to send contact event: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dedup::{Deduplicator, DupePolicy};
+    use crate::receiver::{ContactDeletion, ContactEvent, ContactInfo};
+    use std::time::{Duration, Instant};
+
+    fn contact_info(id: Option<&str>, call: &str, duplicate: bool) -> ContactInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": id,
+            "call": call,
+            "band": "20",
+            "mode": "CW",
+            "duplicate": duplicate,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_process_resend_by_id() {
+        let mut deduplicator = Deduplicator::new(DupePolicy::Show, Duration::from_secs(120));
+        let now = Instant::now();
+        let event = ContactEvent::Insert(contact_info(Some("1"), "W1AW", false));
+
+        assert!(deduplicator.process(event.clone(), now).is_some());
+        assert!(deduplicator
+            .process(event.clone(), now + Duration::from_secs(3600))
+            .is_none());
+
+        let deletion = ContactEvent::Delete(ContactDeletion {
+            id: "1".to_string(),
+            call: None,
+        });
+        assert!(deduplicator.process(deletion, now).is_some());
+        assert!(deduplicator.process(event, now).is_some());
+    }
+
+    #[test]
+    fn test_process_resend_by_key() {
+        let mut deduplicator = Deduplicator::new(DupePolicy::Show, Duration::from_secs(120));
+        let now = Instant::now();
+        let event = ContactEvent::Insert(contact_info(None, "W1AW", false));
+
+        assert!(deduplicator.process(event.clone(), now).is_some());
+        assert!(deduplicator
+            .process(event.clone(), now + Duration::from_secs(60))
+            .is_none());
+        assert!(deduplicator
+            .process(event, now + Duration::from_secs(300))
+            .is_some());
+    }
+
+    #[test]
+    fn test_process_contest_dupes() {
+        let now = Instant::now();
+        let event = ContactEvent::Insert(contact_info(Some("1"), "W1AW", true));
+
+        let mut deduplicator = Deduplicator::new(DupePolicy::Show, Duration::from_secs(120));
+        assert_eq!(
+            deduplicator.process(event.clone(), now),
+            Some(event.clone())
+        );
+
+        let mut deduplicator = Deduplicator::new(DupePolicy::Hide, Duration::from_secs(120));
+        assert_eq!(deduplicator.process(event, now), None);
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum DxccError {
+    IO(std::io::Error),
+    MalformedRecord(String),
+}
+
+impl Display for DxccError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DxccError::IO(e) => {
+                write!(f, "IO error: {}", e)
+            }
+            DxccError::MalformedRecord(e) => {
+                write!(f, "Malformed record: {}", e)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for DxccError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DxccInfo {
+    pub entity: String,
+    pub prefix: String,
+    pub continent: String,
+    pub cq_zone: u8,
+    pub itu_zone: u8,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct PrefixEntry {
+    entity: usize,
+    cq_zone: Option<u8>,
+    itu_zone: Option<u8>,
+    continent: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Debug, Default)]
+pub struct DxccResolver {
+    entities: Vec<DxccInfo>,
+    prefixes: HashMap<String, PrefixEntry>,
+    calls: HashMap<String, PrefixEntry>,
+    max_prefix_len: usize,
+}
+
+impl DxccResolver {
+    pub fn load(path: &Path) -> Result<Self, DxccError> {
+        let payload = std::fs::read(path)?;
+        let payload = String::from_utf8_lossy(&payload);
+
+        let is_csv = path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+        let resolver = match is_csv {
+            true => Self::from_csv(&payload)?,
+            false => Self::from_dat(&payload)?,
+        };
+
+        log::info!(
+            "Loaded {} DXCC entities and {} prefixes from {}",
+            resolver.entities.len(),
+            resolver.prefixes.len() + resolver.calls.len(),
+            path.display()
+        );
+        Ok(resolver)
+    }
+
+    pub fn from_dat(payload: &str) -> Result<Self, DxccError> {
+        let mut resolver = Self::default();
+
+        for record in payload.split(';') {
+            let record = record.trim();
+            if record.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = record.splitn(9, ':').map(str::trim).collect();
+            if fields.len() != 9 {
+                return Err(DxccError::MalformedRecord(record.to_string()));
+            }
+
+            let entity = parse_entity(
+                fields[0], fields[7], fields[3], fields[1], fields[2], fields[4], fields[5],
+            )
+            .ok_or(DxccError::MalformedRecord(record.to_string()))?;
+            resolver.add_entity(entity, fields[8].split(',').map(str::trim))?;
+        }
+
+        Ok(resolver)
+    }
+
+    pub fn from_csv(payload: &str) -> Result<Self, DxccError> {
+        let mut resolver = Self::default();
+
+        for line in payload.lines() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.splitn(10, ',').map(str::trim).collect();
+            if fields.len() != 10 {
+                return Err(DxccError::MalformedRecord(line.to_string()));
+            }
+
+            let entity = parse_entity(
+                fields[1], fields[0], fields[3], fields[4], fields[5], fields[6], fields[7],
+            )
+            .ok_or(DxccError::MalformedRecord(line.to_string()))?;
+            resolver.add_entity(entity, fields[9].trim_end_matches(';').split_whitespace())?;
+        }
+
+        Ok(resolver)
+    }
+
+    pub fn resolve(&self, call: &str) -> Option<DxccInfo> {
+        let call = call.trim().to_uppercase();
+
+        let entry = self.calls.get(&call).or_else(|| {
+            (1..=call.len().min(self.max_prefix_len))
+                .rev()
+                .find_map(|len| call.get(..len).and_then(|prefix| self.prefixes.get(prefix)))
+        })?;
+
+        let mut dxcc_info = self.entities[entry.entity].clone();
+        if let Some(cq_zone) = entry.cq_zone {
+            dxcc_info.cq_zone = cq_zone;
+        }
+        if let Some(itu_zone) = entry.itu_zone {
+            dxcc_info.itu_zone = itu_zone;
+        }
+        if let Some(continent) = &entry.continent {
+            dxcc_info.continent = continent.clone();
+        }
+        if let (Some(latitude), Some(longitude)) = (entry.latitude, entry.longitude) {
+            dxcc_info.latitude = latitude;
+            dxcc_info.longitude = longitude;
+        }
+
+        Some(dxcc_info)
+    }
+
+    fn add_entity<'a>(
+        &mut self,
+        entity: DxccInfo,
+        prefixes: impl Iterator<Item = &'a str>,
+    ) -> Result<(), DxccError> {
+        let index = self.entities.len();
+        self.entities.push(entity);
+
+        for prefix in prefixes.filter(|prefix| !prefix.is_empty()) {
+            let (exact, prefix) = match prefix.strip_prefix('=') {
+                Some(prefix) => (true, prefix),
+                None => (false, prefix),
+            };
+
+            let (name, entry) = parse_prefix(prefix, index)
+                .ok_or(DxccError::MalformedRecord(prefix.to_string()))?;
+            if exact {
+                self.calls.insert(name, entry);
+            } else {
+                self.max_prefix_len = self.max_prefix_len.max(name.len());
+                self.prefixes.insert(name, entry);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_entity(
+    name: &str,
+    prefix: &str,
+    continent: &str,
+    cq_zone: &str,
+    itu_zone: &str,
+    latitude: &str,
+    longitude: &str,
+) -> Option<DxccInfo> {
+    Some(DxccInfo {
+        entity: name.to_string(),
+        prefix: prefix.trim_start_matches('*').to_string(),
+        continent: continent.to_string(),
+        cq_zone: cq_zone.parse().ok()?,
+        itu_zone: itu_zone.parse().ok()?,
+        latitude: latitude.parse().ok()?,
+        longitude: -longitude.parse::<f64>().ok()?,
+    })
+}
+
+fn parse_prefix(prefix: &str, entity: usize) -> Option<(String, PrefixEntry)> {
+    let end = prefix
+        .find(['(', '[', '<', '{', '~'])
+        .unwrap_or(prefix.len());
+    let (name, mut modifiers) = prefix.split_at(end);
+
+    let mut entry = PrefixEntry {
+        entity,
+        ..Default::default()
+    };
+
+    while let Some(open) = modifiers.chars().next() {
+        let close = match open {
+            '(' => ')',
+            '[' => ']',
+            '<' => '>',
+            '{' => '}',
+            '~' => '~',
+            _ => return None,
+        };
+        let end = modifiers[1..].find(close)? + 1;
+        let value = &modifiers[1..end];
+        match open {
+            '(' => entry.cq_zone = Some(value.parse().ok()?),
+            '[' => entry.itu_zone = Some(value.parse().ok()?),
+            '<' => {
+                let (latitude, longitude) = value.split_once('/')?;
+                entry.latitude = Some(latitude.parse().ok()?);
+                entry.longitude = Some(-longitude.parse::<f64>().ok()?);
+            }
+            '{' => entry.continent = Some(value.to_string()),
+            _ => {}
+        }
+        modifiers = &modifiers[end + 1..];
+    }
+
+    Some((name.to_uppercase(), entry))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::dxcc::{DxccInfo, DxccResolver};
+
+    const CTY_DAT: &str =
+        "Sardinia:                 15:  28:  EU:   40.00:    -9.00:    -1.0:  *IS:
+    IM0,IS,IW0U,IW0V,IW0W,IW0X,IW0Y,IW0Z,=II0M,=IQ0AG;
+Italy:                    15:  28:  EU:   42.82:   -12.58:    -1.0:  I:
+    I,=4U0WFP(14)[27],=4U13FEB;
+United States:            05:  08:  NA:   37.53:    91.67:     5.0:  K:
+    AA,K,N,W,=KH6BB(31)[61]{OC}<21.3/157.9>;
+";
+
+    const CTY_CSV: &str = "*IS,Sardinia,225,EU,15,28,40.00,-9.00,-1.0,IM0 IS IW0U =II0M;
+I,Italy,248,EU,15,28,42.82,-12.58,-1.0,I =4U13FEB;
+";
+
+    #[test]
+    fn test_resolve_dat() {
+        let resolver = DxccResolver::from_dat(CTY_DAT).unwrap();
+
+        let expected = DxccInfo {
+            entity: "Sardinia".to_string(),
+            prefix: "IS".to_string(),
+            continent: "EU".to_string(),
+            cq_zone: 15,
+            itu_zone: 28,
+            latitude: 40.0,
+            longitude: 9.0,
+        };
+
+        assert_eq!(resolver.resolve("is0gvh"), Some(expected.clone()));
+        assert_eq!(resolver.resolve("II0M"), Some(expected));
+        assert_eq!(resolver.resolve("IK0ABC").unwrap().entity, "Italy");
+        assert_eq!(resolver.resolve("II0MA").unwrap().entity, "Italy");
+        assert_eq!(resolver.resolve("W1AW").unwrap().longitude, -91.67);
+        assert_eq!(resolver.resolve("ZZ9ZZZ"), None);
+    }
+
+    #[test]
+    fn test_resolve_dat_overrides() {
+        let resolver = DxccResolver::from_dat(CTY_DAT).unwrap();
+
+        let expected = DxccInfo {
+            entity: "United States".to_string(),
+            prefix: "K".to_string(),
+            continent: "OC".to_string(),
+            cq_zone: 31,
+            itu_zone: 61,
+            latitude: 21.3,
+            longitude: -157.9,
+        };
+
+        assert_eq!(resolver.resolve("KH6BB"), Some(expected));
+        assert_eq!(resolver.resolve("4U0WFP").unwrap().cq_zone, 14);
+    }
+
+    #[test]
+    fn test_resolve_csv() {
+        let resolver = DxccResolver::from_csv(CTY_CSV).unwrap();
+
+        assert_eq!(resolver.resolve("IS0GVH").unwrap().entity, "Sardinia");
+        assert_eq!(resolver.resolve("IS0GVH").unwrap().prefix, "IS");
+        assert_eq!(resolver.resolve("II0M").unwrap().entity, "Sardinia");
+        assert_eq!(resolver.resolve("4U13FEB").unwrap().entity, "Italy");
+        assert_eq!(resolver.resolve("I1ABC").unwrap().longitude, 12.58);
+    }
+
+    #[test]
+    fn test_from_dat_malformed() {
+        assert!(DxccResolver::from_dat("Sardinia: 15: 28: EU;").is_err());
+    }
+}
@@ -14,36 +14,108 @@
  *
  */
 
-use crate::qrzcom;
-use crate::receiver::ContactInfo;
+use crate::cache::{CallsignCache, LookupResult};
+use crate::callbook::{CallbookError, CallbookProvider, CallbookRecord};
+use crate::callsign::{Callsign, CallsignLocation};
+use crate::deadletter::DeadLetters;
+use crate::dxcc::{DxccInfo, DxccResolver};
+use crate::maidenhead;
+use crate::models::{PathMetrics, Point};
+use crate::overrides::{LocationOverride, OverrideTable};
+use crate::receiver::{ContactEvent, ContactInfo};
 use async_broadcast::Sender;
 use async_channel::Receiver;
+use chrono::Utc;
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::{mpsc, oneshot, Semaphore};
+use tokio::task::JoinHandle;
+use tokio::time::{Interval, MissedTickBehavior};
 
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QSO {
-    call: String,
-    band: String,
-    latitude: f64,
-    longitude: f64,
+    #[serde(flatten)]
+    pub contact_info: ContactInfo,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+    pub location_quality: LocationQuality,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dxcc: Option<DxccInfo>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathMetrics>,
 }
 
 impl Display for QSO {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{} ({}) [{} {}]",
-            self.call, self.band, self.latitude, self.longitude
-        )
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => write!(
+                f,
+                "{} [{} {} {}]",
+                self.contact_info, latitude, longitude, self.location_quality
+            ),
+            _ => write!(f, "{} [{}]", self.contact_info, self.location_quality),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LocationQuality {
+    Exact,
+    Grid,
+    DxccCentroid,
+    Unknown,
+}
+
+impl Display for LocationQuality {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocationQuality::Exact => write!(f, "exact"),
+            LocationQuality::Grid => write!(f, "grid"),
+            LocationQuality::DxccCentroid => write!(f, "dxcc centroid"),
+            LocationQuality::Unknown => write!(f, "unknown"),
+        }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum UnknownLocationPolicy {
+    Drop,
+    Hold,
+    Unlocated,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum MapEvent {
+    Add(QSO),
+    Update(QSO),
+    Remove { id: String },
+}
+
+impl Display for MapEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapEvent::Add(qso) => write!(f, "add {}", qso),
+            MapEvent::Update(qso) => write!(f, "update {}", qso),
+            MapEvent::Remove { id } => write!(f, "remove [{}]", id),
+        }
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
 #[derive(Debug)]
 pub enum EnricherError {
     RecvError(async_channel::RecvError),
-    SendError(async_broadcast::SendError<QSO>),
-    QRZComError(qrzcom::QRZComError),
+    SendError(Box<async_broadcast::SendError<MapEvent>>),
+    CallbookError(CallbookError),
 }
 
 impl Display for EnricherError {
@@ -55,8 +127,8 @@ impl Display for EnricherError {
             EnricherError::SendError(e) => {
                 write!(f, "Send error: {}", e)
             }
-            EnricherError::QRZComError(e) => {
-                write!(f, "QRZ.com error: {}", e)
+            EnricherError::CallbookError(e) => {
+                write!(f, "Callbook error: {}", e)
             }
         }
     }
@@ -68,60 +140,1032 @@ impl From<async_channel::RecvError> for EnricherError {
     }
 }
 
-impl From<async_broadcast::SendError<QSO>> for EnricherError {
-    fn from(value: async_broadcast::SendError<QSO>) -> Self {
-        Self::SendError(value)
+impl From<async_broadcast::SendError<MapEvent>> for EnricherError {
+    fn from(value: async_broadcast::SendError<MapEvent>) -> Self {
+        Self::SendError(Box::new(value))
     }
 }
 
-impl From<qrzcom::QRZComError> for EnricherError {
-    fn from(value: qrzcom::QRZComError) -> Self {
-        Self::QRZComError(value)
+impl From<CallbookError> for EnricherError {
+    fn from(value: CallbookError) -> Self {
+        Self::CallbookError(value)
+    }
+}
+
+pub struct EnricherContext {
+    pub home_point: Point,
+    pub callbook_provider: Box<dyn CallbookProvider>,
+    pub callsign_cache: Arc<CallsignCache>,
+    pub dxcc_resolver: Option<DxccResolver>,
+    pub overrides: Arc<OverrideTable>,
+    pub unknown_location_policy: UnknownLocationPolicy,
+    pub hold_interval: Duration,
+    pub hold_attempts: u32,
+    pub workers: usize,
+    pub preserve_order: bool,
+    pub retry_attempts: u32,
+    pub retry_delay: Duration,
+    pub dead_letters: Arc<DeadLetters>,
+}
+
+struct HeldContact {
+    contact_info: ContactInfo,
+    replace: bool,
+    attempts: u32,
+}
+
+type HeldContacts = Mutex<Vec<HeldContact>>;
+
+enum Job {
+    Event(ContactEvent),
+    Retry(HeldContact),
+}
+
+impl Job {
+    fn id(&self) -> Option<String> {
+        match self {
+            Job::Event(ContactEvent::Insert(contact_info))
+            | Job::Event(ContactEvent::Replace(contact_info))
+            | Job::Retry(HeldContact { contact_info, .. }) => contact_info.id.clone(),
+            Job::Event(ContactEvent::Delete(contact_deletion)) => Some(contact_deletion.id.clone()),
+        }
+    }
+
+    async fn run(self, context: &EnricherContext, held_contacts: &HeldContacts) -> Vec<MapEvent> {
+        match self {
+            Job::Event(contact_event) => process(context, contact_event, held_contacts).await,
+            Job::Retry(held_contact) => {
+                log::debug!(
+                    "Retrying held contact {} (attempt {})",
+                    held_contact.contact_info,
+                    held_contact.attempts
+                );
+                locate(
+                    context,
+                    held_contact.contact_info,
+                    held_contact.replace,
+                    held_contact.attempts,
+                    held_contacts,
+                )
+                .await
+                .into_iter()
+                .collect()
+            }
+        }
     }
 }
 
 pub async fn run_enricher(
-    qrzcom_user: &str,
-    qrzcom_password: &str,
-    contact_info_receiver: Receiver<ContactInfo>,
-    qso_sender: Sender<QSO>,
+    context: EnricherContext,
+    contact_event_receiver: Receiver<ContactEvent>,
+    map_event_sender: Sender<MapEvent>,
 ) -> Result<(), EnricherError> {
+    let workers = context.workers.max(1);
+    let context = Arc::new(context);
+    let held_contacts: Arc<HeldContacts> = Arc::default();
+    let semaphore = Arc::new(Semaphore::new(workers));
+    let mut pending: HashMap<String, JoinHandle<()>> = HashMap::new();
+
+    let ordered_sender = match context.preserve_order {
+        true => {
+            let (ordered_sender, ordered_receiver) = mpsc::channel(workers);
+            tokio::spawn(run_ordered_broadcaster(
+                ordered_receiver,
+                map_event_sender.clone(),
+            ));
+            Some(ordered_sender)
+        }
+        false => None,
+    };
+
+    let mut hold_interval = match context.unknown_location_policy {
+        UnknownLocationPolicy::Hold => {
+            let mut hold_interval = tokio::time::interval(context.hold_interval);
+            hold_interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            Some(hold_interval)
+        }
+        _ => None,
+    };
+
     loop {
-        let contact_info = contact_info_receiver.recv().await;
-        if contact_info.is_err() {
+        let jobs: Vec<Job> = tokio::select! {
+            contact_event = contact_event_receiver.recv() => match contact_event {
+                Ok(contact_event) => {
+                    log::debug!("Contact event to enrich: {}", contact_event);
+                    vec![Job::Event(contact_event)]
+                }
+                Err(e) => return Err(e.into()),
+            },
+            _ = hold_tick(&mut hold_interval) => {
+                std::mem::take(&mut *held_contacts.lock().unwrap())
+                    .into_iter()
+                    .map(Job::Retry)
+                    .collect()
+            }
+        };
+
+        for job in jobs {
+            let permit = semaphore
+                .clone()
+                .acquire_owned()
+                .await
+                .expect("Enricher semaphore closed");
+
+            let id = job.id();
+            pending.retain(|_, handle| !handle.is_finished());
+            let previous = id.as_ref().and_then(|id| pending.remove(id));
+
+            let (result_sender, result_receiver) = match ordered_sender {
+                Some(_) => {
+                    let (result_sender, result_receiver) = oneshot::channel();
+                    (Some(result_sender), Some(result_receiver))
+                }
+                None => (None, None),
+            };
+
+            let context = context.clone();
+            let held_contacts = held_contacts.clone();
+            let map_event_sender = map_event_sender.clone();
+            let handle = tokio::spawn(async move {
+                if let Some(previous) = previous {
+                    let _ = previous.await;
+                }
+
+                let map_events = job.run(&context, &held_contacts).await;
+                drop(permit);
+
+                match result_sender {
+                    Some(result_sender) => {
+                        let _ = result_sender.send(map_events);
+                    }
+                    None => broadcast(&map_event_sender, map_events).await,
+                }
+            });
+
+            if let (Some(ordered_sender), Some(result_receiver)) =
+                (&ordered_sender, result_receiver)
+            {
+                if let Err(e) = ordered_sender.send(result_receiver).await {
+                    log::warn!("Error sending enrichment result: {}", e);
+                }
+            }
+
+            if let Some(id) = id {
+                pending.insert(id, handle);
+            }
+        }
+    }
+}
+
+async fn hold_tick(hold_interval: &mut Option<Interval>) {
+    match hold_interval {
+        Some(hold_interval) => {
+            hold_interval.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+async fn run_ordered_broadcaster(
+    mut ordered_receiver: mpsc::Receiver<oneshot::Receiver<Vec<MapEvent>>>,
+    map_event_sender: Sender<MapEvent>,
+) {
+    while let Some(result_receiver) = ordered_receiver.recv().await {
+        if let Ok(map_events) = result_receiver.await {
+            broadcast(&map_event_sender, map_events).await;
+        }
+    }
+}
+
+async fn broadcast(map_event_sender: &Sender<MapEvent>, map_events: Vec<MapEvent>) {
+    for map_event in map_events {
+        log::trace!("Broadcasting map event: {}", map_event);
+        if let Err(e) = map_event_sender.broadcast(map_event).await {
+            log::warn!("Error sending map event: {}", e);
+        }
+    }
+}
+
+async fn process(
+    context: &EnricherContext,
+    contact_event: ContactEvent,
+    held_contacts: &HeldContacts,
+) -> Vec<MapEvent> {
+    match contact_event {
+        ContactEvent::Insert(contact_info) => {
+            locate(context, contact_info, false, 0, held_contacts).await
+        }
+        ContactEvent::Replace(contact_info) => {
+            release_held(held_contacts, contact_info.id.as_deref());
+            if let Some(id) = &contact_info.id {
+                context.dead_letters.remove_contact(id);
+            }
+            locate(context, contact_info, true, 0, held_contacts).await
+        }
+        ContactEvent::Delete(contact_deletion) => {
+            release_held(held_contacts, Some(&contact_deletion.id));
+            context.dead_letters.remove_contact(&contact_deletion.id);
+            Some(MapEvent::Remove {
+                id: contact_deletion.id,
+            })
+        }
+    }
+    .into_iter()
+    .collect()
+}
+
+fn release_held(held_contacts: &HeldContacts, id: Option<&str>) {
+    if id.is_some() {
+        held_contacts
+            .lock()
+            .unwrap()
+            .retain(|held_contact| held_contact.contact_info.id.as_deref() != id);
+    }
+}
+
+async fn locate(
+    context: &EnricherContext,
+    contact_info: ContactInfo,
+    replace: bool,
+    attempts: u32,
+    held_contacts: &HeldContacts,
+) -> Option<MapEvent> {
+    let (qso, lookup_error) = enrich(context, contact_info).await;
+
+    let published = qso.location_quality != LocationQuality::Unknown
+        || context.unknown_location_policy == UnknownLocationPolicy::Unlocated;
+    let held = !published
+        && context.unknown_location_policy == UnknownLocationPolicy::Hold
+        && attempts < context.hold_attempts;
+    if let Some(lookup_error) = lookup_error.filter(|e| !published && !held && e.is_not_found()) {
+        log::info!("Moving {} to the dead-letter list", qso.contact_info);
+        context.dead_letters.push(
+            qso.contact_info.clone(),
+            lookup_error.to_string(),
+            Utc::now(),
+        );
+    }
+
+    if published {
+        return match (replace, &qso.contact_info.id) {
+            (true, Some(_)) => Some(MapEvent::Update(qso)),
+            _ => Some(MapEvent::Add(qso)),
+        };
+    }
+
+    match context.unknown_location_policy {
+        UnknownLocationPolicy::Hold if held => {
+            log::info!("Holding {} until its location is known", qso.contact_info);
+            held_contacts.lock().unwrap().push(HeldContact {
+                contact_info: qso.contact_info.clone(),
+                replace,
+                attempts: attempts + 1,
+            });
+        }
+        UnknownLocationPolicy::Hold => {
             log::warn!(
-                "Error receiving contact info: {}",
-                contact_info.unwrap_err()
+                "Giving up locating {} after {} attempts",
+                qso.contact_info,
+                attempts
             );
-            continue;
         }
-        let contact_info = contact_info?;
+        _ => {
+            log::info!("Dropping {}: unknown location", qso.contact_info);
+        }
+    }
 
-        log::debug!("Contact info to enrich: {}", contact_info);
+    match (replace, attempts, qso.contact_info.id) {
+        (true, 0, Some(id)) => Some(MapEvent::Remove { id }),
+        _ => None,
+    }
+}
 
-        let callsign = qrzcom::call_xml_api(qrzcom_user, qrzcom_password, &contact_info.call).await;
-        if callsign.is_err() {
-            log::warn!("Error retrieving callsign: {}", callsign.unwrap_err());
-            continue;
+async fn enrich(
+    context: &EnricherContext,
+    mut contact_info: ContactInfo,
+) -> (QSO, Option<Arc<CallbookError>>) {
+    let callsign = Callsign::parse(&contact_info.call);
+    let callsign_location = callsign.location();
+    let dxcc = context
+        .dxcc_resolver
+        .as_ref()
+        .and_then(|dxcc_resolver| match &callsign_location {
+            CallsignLocation::Base => dxcc_resolver.resolve(&callsign.base),
+            CallsignLocation::Foreign(prefix) => dxcc_resolver.resolve(prefix),
+            CallsignLocation::NotLocatable => None,
+        });
+
+    if contact_info.grid.is_none() {
+        contact_info.grid = exchange_locator(&contact_info);
+    }
+
+    let mut lookup_error = None;
+    let location_override = match &callsign_location {
+        CallsignLocation::Base => context
+            .overrides
+            .resolve(&contact_info.call)
+            .or_else(|| context.overrides.resolve(&callsign.base)),
+        _ => context.overrides.resolve(&contact_info.call),
+    };
+
+    let (location, location_quality) = match (
+        contact_info.location,
+        locator_location(contact_info.grid.as_deref()),
+        location_override,
+        &callsign_location,
+    ) {
+        (Some(location), _, _, _) => (Some(location), LocationQuality::Exact),
+        (None, Some(location), _, _) => (Some(location), LocationQuality::Grid),
+        (None, None, Some(location_override), _) => {
+            log::debug!(
+                "Using override {} for {}",
+                location_override,
+                contact_info.call
+            );
+            if contact_info.grid.is_none() {
+                contact_info.grid = location_override.grid.clone();
+            }
+            match override_location(&location_override) {
+                Some((location, location_quality)) => (Some(location), location_quality),
+                None => (None, LocationQuality::Unknown),
+            }
+        }
+        (None, None, None, CallsignLocation::NotLocatable) => {
+            log::debug!("{} is not locatable", contact_info.call);
+            (None, LocationQuality::Unknown)
         }
-        let callsign = callsign?;
+        (None, None, None, CallsignLocation::Foreign(prefix)) => match &dxcc {
+            Some(dxcc) => (Some(dxcc_location(dxcc)), LocationQuality::DxccCentroid),
+            None => {
+                log::warn!(
+                    "Unable to resolve prefix {} of {}, using callbook location",
+                    prefix,
+                    contact_info.call
+                );
+                let result = lookup(context, &callsign.base).await;
+                callbook_location(&mut contact_info, result, None, &mut lookup_error)
+            }
+        },
+        (None, None, None, CallsignLocation::Base) => {
+            let result = lookup(context, &callsign.base).await;
+            callbook_location(&mut contact_info, result, dxcc.as_ref(), &mut lookup_error)
+        }
+    };
 
-        let qso: QSO = QSO {
-            call: contact_info.call,
-            band: contact_info.band,
-            latitude: callsign.lat.unwrap_or(0.0),
-            longitude: callsign.lon.unwrap_or(0.0),
-        };
-        log::debug!("QSO:: {}", qso);
+    if let (None, Some(location), LocationQuality::Exact) =
+        (&contact_info.grid, &location, location_quality)
+    {
+        contact_info.grid = maidenhead::encode(location, 6).ok();
+    }
+
+    let path = location
+        .map(|location| PathMetrics::new(&context.home_point, &location, contact_info.long_path));
+
+    let qso: QSO = QSO {
+        contact_info,
+        latitude: location.map(|location| location.latitude),
+        longitude: location.map(|location| location.longitude),
+        location_quality,
+        dxcc,
+        path,
+    };
+    log::debug!("QSO:: {}", qso);
+
+    (qso, lookup_error)
+}
+
+fn callbook_location(
+    contact_info: &mut ContactInfo,
+    result: LookupResult,
+    dxcc: Option<&DxccInfo>,
+    lookup_error: &mut Option<Arc<CallbookError>>,
+) -> (Option<Point>, LocationQuality) {
+    if let Ok(record) = &result {
+        if contact_info.grid.is_none() {
+            contact_info.grid = record.grid.clone();
+        }
+    }
+
+    match (result.map(|record| record_location(&record)), dxcc) {
+        (Ok(Some(located)), _) => (Some(located.0), located.1),
+        (Ok(None), Some(dxcc)) => {
+            log::debug!(
+                "No coordinates for {}, using {} centroid",
+                contact_info.call,
+                dxcc.entity
+            );
+            (Some(dxcc_location(dxcc)), LocationQuality::DxccCentroid)
+        }
+        (Err(e), Some(dxcc)) => {
+            log::debug!(
+                "Lookup of {} failed ({}), using {} centroid",
+                contact_info.call,
+                e,
+                dxcc.entity
+            );
+            *lookup_error = Some(e);
+            (Some(dxcc_location(dxcc)), LocationQuality::DxccCentroid)
+        }
+        (Ok(None), None) => {
+            log::warn!("No coordinates for {}", contact_info.call);
+            (None, LocationQuality::Unknown)
+        }
+        (Err(e), None) => {
+            log::warn!("Lookup of {} failed: {}", contact_info.call, e);
+            *lookup_error = Some(e);
+            (None, LocationQuality::Unknown)
+        }
+    }
+}
+
+fn exchange_locator(contact_info: &ContactInfo) -> Option<String> {
+    [
+        &contact_info.exchange1,
+        &contact_info.exchange2,
+        &contact_info.exchange3,
+    ]
+    .into_iter()
+    .flatten()
+    .find(|exchange| maidenhead::is_locator(exchange))
+    .map(|exchange| exchange.trim().to_uppercase())
+}
 
-        if qso.latitude == 0.0 && qso.longitude == 0.0 {
-            log::warn!("Latitude or longitude are empty");
+fn locator_location(locator: Option<&str>) -> Option<Point> {
+    let locator = locator?;
+    match maidenhead::decode(locator) {
+        Ok(location) => Some(location),
+        Err(e) => {
+            log::debug!("Ignoring locator {}: {}", locator, e);
+            None
         }
+    }
+}
+
+fn record_location(record: &CallbookRecord) -> Option<(Point, LocationQuality)> {
+    match (record.latitude, record.longitude) {
+        (Some(latitude), Some(longitude)) => Some((
+            Point {
+                latitude,
+                longitude,
+            },
+            LocationQuality::Exact,
+        )),
+        _ => locator_location(record.grid.as_deref())
+            .map(|location| (location, LocationQuality::Grid)),
+    }
+}
+
+fn override_location(location_override: &LocationOverride) -> Option<(Point, LocationQuality)> {
+    match location_override.location() {
+        Some(location) => Some((location, LocationQuality::Exact)),
+        None => locator_location(location_override.grid.as_deref())
+            .map(|location| (location, LocationQuality::Grid)),
+    }
+}
 
-        log::trace!("Broadcasting QSO");
-        let result = qso_sender.broadcast(qso).await;
-        if result.is_err() {
-            log::warn!("Error sending QSO: {}", result.unwrap_err());
+fn dxcc_location(dxcc: &DxccInfo) -> Point {
+    Point {
+        latitude: dxcc.latitude,
+        longitude: dxcc.longitude,
+    }
+}
+
+async fn lookup(context: &EnricherContext, call: &str) -> LookupResult {
+    context
+        .callsign_cache
+        .get_or_lookup(call, Utc::now(), lookup_with_retry(context, call))
+        .await
+}
+
+async fn lookup_with_retry(
+    context: &EnricherContext,
+    call: &str,
+) -> Result<CallbookRecord, CallbookError> {
+    let mut attempt = 0;
+
+    loop {
+        match context.callbook_provider.lookup(call).await {
+            Err(e) if e.is_transient() && attempt < context.retry_attempts => {
+                let delay = context
+                    .retry_delay
+                    .saturating_mul(2u32.saturating_pow(attempt))
+                    .min(MAX_RETRY_DELAY);
+                attempt += 1;
+                log::info!(
+                    "Lookup of {} failed ({}), retry {}/{} in {:?}",
+                    call,
+                    e,
+                    attempt,
+                    context.retry_attempts,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::CallsignCache;
+    use crate::callbook::{CallbookChain, CallbookError, CallbookProvider, CallbookRecord};
+    use crate::dxcc::DxccResolver;
+    use crate::enricher::{
+        enrich, locate, run_enricher, EnricherContext, LocationQuality, MapEvent,
+        UnknownLocationPolicy,
+    };
+    use crate::models::Point;
+    use crate::overrides::{LocationOverride, OverrideTable};
+    use crate::qrzcom::QRZComError;
+    use crate::receiver::{ContactEvent, ContactInfo};
+    use async_trait::async_trait;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn context(unknown_location_policy: UnknownLocationPolicy) -> EnricherContext {
+        EnricherContext {
+            home_point: Point {
+                latitude: 39.2238,
+                longitude: 9.1217,
+            },
+            callbook_provider: Box::new(CallbookChain::default()),
+            callsign_cache: Arc::new(CallsignCache::new(None, Duration::from_secs(3600))),
+            dxcc_resolver: None,
+            overrides: Arc::default(),
+            unknown_location_policy,
+            hold_interval: Duration::from_secs(60),
+            hold_attempts: 2,
+            workers: 1,
+            preserve_order: false,
+            retry_attempts: 2,
+            retry_delay: Duration::from_millis(1),
+            dead_letters: Arc::default(),
+        }
+    }
+
+    fn contact_info(value: serde_json::Value) -> ContactInfo {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enrich_exchange_locator() {
+        let contact_info = contact_info(serde_json::json!({
+            "call": "I3ABC", "band": "144", "exchange1": "001", "exchange2": "jn55vk"
+        }));
+
+        let qso = enrich(&context(UnknownLocationPolicy::Unlocated), contact_info)
+            .await
+            .0;
+
+        assert_eq!(qso.contact_info.grid, Some("JN55VK".to_string()));
+        assert_eq!(qso.location_quality, LocationQuality::Grid);
+        assert!((qso.latitude.unwrap() - 45.437500).abs() < 1e-6);
+        assert!((qso.longitude.unwrap() - 11.791666).abs() < 1e-6);
+        assert!(qso
+            .path
+            .is_some_and(|path| path.bearing < 90.0 && !path.long_path));
+    }
+
+    #[tokio::test]
+    async fn test_enrich_location_grid() {
+        let contact_info = contact_info(serde_json::json!({
+            "call": "W1AW", "band": "20",
+            "location": {"latitude": 41.714775, "longitude": -72.727260}
+        }));
+
+        let qso = enrich(&context(UnknownLocationPolicy::Unlocated), contact_info)
+            .await
+            .0;
+
+        assert_eq!(qso.contact_info.grid, Some("FN31PR".to_string()));
+        assert_eq!(qso.location_quality, LocationQuality::Exact);
+        assert_eq!(qso.latitude, Some(41.714775));
+    }
+
+    #[tokio::test]
+    async fn test_enrich_dxcc_centroid() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.dxcc_resolver = Some(
+            DxccResolver::from_dat(
+                "Sardinia: 15: 28: EU: 40.00: -9.00: -1.0: *IS:
+    IM0,IS;",
+            )
+            .unwrap(),
+        );
+        let contact_info = contact_info(serde_json::json!({"call": "IS0GVH", "band": "20"}));
+
+        let qso = enrich(&context, contact_info).await.0;
+
+        assert_eq!(qso.location_quality, LocationQuality::DxccCentroid);
+        assert_eq!(qso.latitude, Some(40.0));
+        assert_eq!(qso.longitude, Some(9.0));
+        assert_eq!(qso.contact_info.grid, None);
+    }
+
+    #[tokio::test]
+    async fn test_enrich_compound_callsign() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.dxcc_resolver = Some(
+            DxccResolver::from_dat(
+                "Sardinia: 15: 28: EU: 40.00: -9.00: -1.0: *IS:
+    IM0,IS;
+Canary Islands: 33: 36: AF: 28.32: 15.85: 0.0: EA8:
+    EA8,EH8;",
+            )
+            .unwrap(),
+        );
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "EA8/IS0GVH/P", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::DxccCentroid);
+        assert_eq!(qso.dxcc.unwrap().entity, "Canary Islands");
+        assert_eq!(qso.latitude, Some(28.32));
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "IS0GVH/MM", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Unknown);
+        assert_eq!(qso.dxcc, None);
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({
+                "call": "IS0GVH/MM", "band": "20", "grid": "JM49"
+            })),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Grid);
+    }
+
+    #[tokio::test]
+    async fn test_enrich_foreign_prefix_lookup() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.callbook_provider = Box::new(FlakyProvider(AtomicU32::new(0)));
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "EA8/IS0GVH/P", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Exact);
+        assert_eq!(qso.latitude, Some(39.2));
+
+        context.dxcc_resolver = Some(
+            DxccResolver::from_dat(
+                "Canary Islands: 33: 36: AF: 28.32: 15.85: 0.0: EA8:
+    EA8,EH8;",
+            )
+            .unwrap(),
+        );
+
+        let (qso, lookup_error) = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "EA8/IS0XXX", "band": "20"})),
+        )
+        .await;
+        assert_eq!(qso.location_quality, LocationQuality::DxccCentroid);
+        assert_eq!(qso.latitude, Some(28.32));
+        assert!(lookup_error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_enrich_override() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.callbook_provider = Box::new(SlowProvider);
+        context.overrides = Arc::new(OverrideTable::load(None).unwrap());
+        context
+            .overrides
+            .upsert(LocationOverride {
+                call: Some("K1ABC".to_string()),
+                pattern: None,
+                latitude: Some(41.7),
+                longitude: Some(-72.7),
+                grid: None,
+                note: Some("Club station".to_string()),
+            })
+            .unwrap();
+        context
+            .overrides
+            .upsert(LocationOverride {
+                call: None,
+                pattern: Some("3Y0[A-Z]".to_string()),
+                latitude: None,
+                longitude: None,
+                grid: Some("JD15".to_string()),
+                note: None,
+            })
+            .unwrap();
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "K1ABC/P", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Exact);
+        assert_eq!(qso.latitude, Some(41.7));
+        assert_eq!(qso.contact_info.grid, Some("FN31PQ".to_string()));
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "3Y0J", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Grid);
+        assert_eq!(qso.contact_info.grid, Some("JD15".to_string()));
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "W1AW", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.latitude, Some(1.0));
+    }
+
+    #[tokio::test]
+    async fn test_enrich_unknown() {
+        let contact_info = contact_info(serde_json::json!({"call": "W1AW", "band": "20"}));
+
+        let qso = enrich(&context(UnknownLocationPolicy::Unlocated), contact_info)
+            .await
+            .0;
+
+        assert_eq!(qso.location_quality, LocationQuality::Unknown);
+        assert_eq!(qso.latitude, None);
+        assert_eq!(qso.longitude, None);
+    }
+
+    #[tokio::test]
+    async fn test_locate_unknown_location_policy() {
+        let contact_info =
+            contact_info(serde_json::json!({"id": "1", "call": "W1AW", "band": "20"}));
+        let held_contacts = Mutex::new(Vec::new());
+
+        let context = context(UnknownLocationPolicy::Unlocated);
+        let map_event = locate(&context, contact_info.clone(), false, 0, &held_contacts).await;
+        assert!(matches!(map_event, Some(MapEvent::Add(_))));
+
+        let context = self::context(UnknownLocationPolicy::Drop);
+        let map_event = locate(&context, contact_info.clone(), false, 0, &held_contacts).await;
+        assert!(map_event.is_none());
+        let map_event = locate(&context, contact_info.clone(), true, 0, &held_contacts).await;
+        assert!(matches!(map_event, Some(MapEvent::Remove { id }) if id == "1"));
+        assert!(held_contacts.lock().unwrap().is_empty());
+
+        let context = self::context(UnknownLocationPolicy::Hold);
+        let map_event = locate(&context, contact_info.clone(), false, 0, &held_contacts).await;
+        assert!(map_event.is_none());
+        assert_eq!(held_contacts.lock().unwrap().len(), 1);
+        assert_eq!(held_contacts.lock().unwrap()[0].attempts, 1);
+
+        let map_event = locate(&context, contact_info, false, 2, &held_contacts).await;
+        assert!(map_event.is_none());
+        assert_eq!(held_contacts.lock().unwrap().len(), 1);
+    }
+
+    struct SlowProvider;
+
+    #[async_trait]
+    impl CallbookProvider for SlowProvider {
+        fn name(&self) -> &str {
+            "slow"
+        }
+
+        async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+            if callsign == "SLOW" {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Ok(CallbookRecord {
+                call: callsign.to_string(),
+                latitude: Some(1.0),
+                longitude: Some(1.0),
+                grid: None,
+            })
+        }
+    }
+
+    struct FlakyProvider(AtomicU32);
+
+    #[async_trait]
+    impl CallbookProvider for FlakyProvider {
+        fn name(&self) -> &str {
+            "flaky"
+        }
+
+        async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+            match (callsign, self.0.fetch_add(1, Ordering::Relaxed)) {
+                ("IS0GVH", 0) => Err(QRZComError::Parsing(
+                    serde_xml_rs::from_str::<String>("<").unwrap_err(),
+                )
+                .into()),
+                ("IS0GVH", _) => Ok(CallbookRecord {
+                    call: callsign.to_string(),
+                    latitude: Some(39.2),
+                    longitude: Some(9.1),
+                    grid: None,
+                }),
+                ("IS0BAD", _) => {
+                    Err(QRZComError::ApiError("Invalid session key".to_string()).into())
+                }
+                _ => Err(QRZComError::ApiError(format!("Not found: {}", callsign)).into()),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_enrich_retry_and_dead_letter() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.callbook_provider = Box::new(FlakyProvider(AtomicU32::new(0)));
+
+        let qso = enrich(
+            &context,
+            contact_info(serde_json::json!({"call": "IS0GVH", "band": "20"})),
+        )
+        .await
+        .0;
+        assert_eq!(qso.location_quality, LocationQuality::Exact);
+        assert_eq!(context.dead_letters.len(), 0);
+
+        let held_contacts = Mutex::new(Vec::new());
+        let not_found =
+            contact_info(serde_json::json!({"id": "7", "call": "IS0XXX", "band": "20"}));
+        let map_event = locate(&context, not_found.clone(), false, 0, &held_contacts).await;
+        assert!(matches!(map_event, Some(MapEvent::Add(_))));
+        assert_eq!(context.dead_letters.len(), 0);
+
+        context.unknown_location_policy = UnknownLocationPolicy::Drop;
+        let map_event = locate(&context, not_found, false, 0, &held_contacts).await;
+        assert!(map_event.is_none());
+        let dead_letters = context.dead_letters.list();
+        assert_eq!(dead_letters.len(), 1);
+        assert_eq!(dead_letters[0].contact.call, "IS0XXX");
+        assert!(dead_letters[0].error.contains("Not found"));
+
+        let bad_session = contact_info(serde_json::json!({"call": "IS0BAD", "band": "20"}));
+        locate(&context, bad_session, false, 0, &held_contacts).await;
+        assert_eq!(context.dead_letters.len(), 1);
+
+        context.dxcc_resolver = Some(
+            DxccResolver::from_dat(
+                "Sardinia: 15: 28: EU: 40.00: -9.00: -1.0: IS:
+    IS,IM0;",
+            )
+            .unwrap(),
+        );
+        let centroid = contact_info(serde_json::json!({"call": "IS0YYY", "band": "20"}));
+        let map_event = locate(&context, centroid, false, 0, &held_contacts).await;
+        assert!(matches!(map_event, Some(MapEvent::Add(_))));
+        assert_eq!(context.dead_letters.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_locate_dead_letter_after_hold() {
+        let mut context = context(UnknownLocationPolicy::Hold);
+        context.callbook_provider = Box::new(FlakyProvider(AtomicU32::new(0)));
+        let held_contacts = Mutex::new(Vec::new());
+        let not_found = contact_info(serde_json::json!({"call": "IS0XXX", "band": "20"}));
+
+        for attempts in 0..2 {
+            let map_event =
+                locate(&context, not_found.clone(), false, attempts, &held_contacts).await;
+            assert!(map_event.is_none());
+            assert_eq!(held_contacts.lock().unwrap().len(), 1);
+            assert_eq!(context.dead_letters.len(), 0);
+            held_contacts.lock().unwrap().clear();
+        }
+
+        let map_event = locate(&context, not_found, false, 2, &held_contacts).await;
+        assert!(map_event.is_none());
+        assert!(held_contacts.lock().unwrap().is_empty());
+        assert_eq!(context.dead_letters.len(), 1);
+    }
+
+    struct RecoveringProvider(AtomicU32, u32);
+
+    #[async_trait]
+    impl CallbookProvider for RecoveringProvider {
+        fn name(&self) -> &str {
+            "recovering"
+        }
+
+        async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+            match self.0.fetch_add(1, Ordering::Relaxed) < self.1 {
+                true => Err(QRZComError::ApiError(format!("Not found: {}", callsign)).into()),
+                false => Ok(CallbookRecord {
+                    call: callsign.to_string(),
+                    latitude: Some(39.2),
+                    longitude: Some(9.1),
+                    grid: None,
+                }),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_enricher_retries_held_when_idle() {
+        let mut context = context(UnknownLocationPolicy::Hold);
+        context.callbook_provider = Box::new(RecoveringProvider(AtomicU32::new(0), 1));
+        context.hold_interval = Duration::from_millis(10);
+
+        let (contact_event_sender, contact_event_receiver) = async_channel::unbounded();
+        let (map_event_sender, mut map_event_receiver) = async_broadcast::broadcast(8);
+        tokio::spawn(run_enricher(
+            context,
+            contact_event_receiver,
+            map_event_sender,
+        ));
+
+        let contact_info = contact_info(serde_json::json!({"call": "IS0GVH", "band": "20"}));
+        contact_event_sender
+            .send(ContactEvent::Insert(contact_info))
+            .await
+            .unwrap();
+
+        let map_event = tokio::time::timeout(Duration::from_secs(5), map_event_receiver.recv())
+            .await
+            .expect("Held contact not retried")
+            .unwrap();
+        match map_event {
+            MapEvent::Add(qso) => assert_eq!(qso.location_quality, LocationQuality::Exact),
+            other => panic!("Unexpected event: {}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_enricher_without_hold_interval() {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.hold_interval = Duration::ZERO;
+
+        let (contact_event_sender, contact_event_receiver) = async_channel::unbounded();
+        let (map_event_sender, mut map_event_receiver) = async_broadcast::broadcast(8);
+        let task = tokio::spawn(run_enricher(
+            context,
+            contact_event_receiver,
+            map_event_sender,
+        ));
+
+        let contact_info = contact_info(serde_json::json!({"call": "W1AW", "band": "20"}));
+        contact_event_sender
+            .send(ContactEvent::Insert(contact_info))
+            .await
+            .unwrap();
+
+        let map_event = tokio::time::timeout(Duration::from_secs(5), map_event_receiver.recv())
+            .await
+            .expect("Contact not enriched")
+            .unwrap();
+        assert!(matches!(map_event, MapEvent::Add(_)));
+        assert!(!task.is_finished());
+    }
+
+    async fn enriched_calls(preserve_order: bool) -> Vec<String> {
+        let mut context = context(UnknownLocationPolicy::Unlocated);
+        context.callbook_provider = Box::new(SlowProvider);
+        context.workers = 4;
+        context.preserve_order = preserve_order;
+
+        let (contact_event_sender, contact_event_receiver) = async_channel::unbounded();
+        let (map_event_sender, mut map_event_receiver) = async_broadcast::broadcast(8);
+        tokio::spawn(run_enricher(
+            context,
+            contact_event_receiver,
+            map_event_sender,
+        ));
+
+        for call in ["SLOW", "FAST"] {
+            let contact_info = contact_info(serde_json::json!({"call": call, "band": "20"}));
+            contact_event_sender
+                .send(ContactEvent::Insert(contact_info))
+                .await
+                .unwrap();
+        }
+
+        let mut calls = Vec::new();
+        for _ in 0..2 {
+            match map_event_receiver.recv().await.unwrap() {
+                MapEvent::Add(qso) => calls.push(qso.contact_info.call),
+                other => panic!("Unexpected event: {}", other),
+            }
+        }
+        calls
+    }
+
+    #[tokio::test]
+    async fn test_run_enricher_order() {
+        assert_eq!(enriched_calls(false).await, vec!["FAST", "SLOW"]);
+        assert_eq!(enriched_calls(true).await, vec!["SLOW", "FAST"]);
+    }
+}
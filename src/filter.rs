@@ -0,0 +1,179 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use hmac::{Hmac, Mac};
+use ipnet::IpNet;
+use sha2::Sha256;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+const SIGNATURE_LEN: usize = 64;
+
+#[derive(Debug, PartialEq)]
+pub enum FilterError {
+    AddressNotAllowed(IpAddr),
+    MissingSignature,
+    InvalidSignature,
+}
+
+impl Display for FilterError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FilterError::AddressNotAllowed(e) => {
+                write!(f, "Address not allowed: {}", e)
+            }
+            FilterError::MissingSignature => {
+                write!(f, "Missing signature")
+            }
+            FilterError::InvalidSignature => {
+                write!(f, "Invalid signature")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DatagramFilter {
+    allow_list: Vec<IpNet>,
+    secret: Option<Vec<u8>>,
+    rejected_address: AtomicU64,
+    rejected_signature: AtomicU64,
+}
+
+impl DatagramFilter {
+    pub fn new(allow_list: Vec<IpNet>, secret: Option<String>) -> Self {
+        Self {
+            allow_list,
+            secret: secret.map(|s| s.into_bytes()),
+            ..Default::default()
+        }
+    }
+
+    pub fn check<'a>(
+        &self,
+        addr: &SocketAddr,
+        datagram: &'a [u8],
+    ) -> Result<&'a [u8], FilterError> {
+        let ip = addr.ip().to_canonical();
+        if !self.allow_list.is_empty() && !self.allow_list.iter().any(|net| net.contains(&ip)) {
+            let count = self.rejected_address.fetch_add(1, Ordering::Relaxed) + 1;
+            log::debug!("Rejected datagram from {} ({} so far)", ip, count);
+            return Err(FilterError::AddressNotAllowed(ip));
+        }
+
+        let secret = match &self.secret {
+            Some(secret) => secret,
+            None => return Ok(datagram),
+        };
+
+        match open_envelope(secret, datagram) {
+            Ok(payload) => Ok(payload),
+            Err(e) => {
+                let count = self.rejected_signature.fetch_add(1, Ordering::Relaxed) + 1;
+                log::debug!("Rejected datagram from {}: {} ({} so far)", ip, e, count);
+                Err(e)
+            }
+        }
+    }
+
+    pub fn rejected_address(&self) -> u64 {
+        self.rejected_address.load(Ordering::Relaxed)
+    }
+
+    pub fn rejected_signature(&self) -> u64 {
+        self.rejected_signature.load(Ordering::Relaxed)
+    }
+}
+
+// Signed datagrams carry the hex encoded HMAC-SHA256 of the payload, followed by a newline and
+// the payload itself.
+fn open_envelope<'a>(secret: &[u8], datagram: &'a [u8]) -> Result<&'a [u8], FilterError> {
+    if datagram.len() <= SIGNATURE_LEN || datagram[SIGNATURE_LEN] != b'\n' {
+        return Err(FilterError::MissingSignature);
+    }
+
+    let signature =
+        hex::decode(&datagram[..SIGNATURE_LEN]).map_err(|_| FilterError::MissingSignature)?;
+    let payload = &datagram[SIGNATURE_LEN + 1..];
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).unwrap();
+    mac.update(payload);
+    mac.verify_slice(&signature)
+        .map_err(|_| FilterError::InvalidSignature)?;
+
+    Ok(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{DatagramFilter, FilterError};
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+    use std::net::SocketAddr;
+
+    fn sign(secret: &str, payload: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(payload);
+
+        let mut datagram = hex::encode(mac.finalize().into_bytes()).into_bytes();
+        datagram.push(b'\n');
+        datagram.extend_from_slice(payload);
+        datagram
+    }
+
+    #[test]
+    fn test_check_allow_list() {
+        let filter = DatagramFilter::new(
+            vec![
+                "192.168.1.0/24".parse().unwrap(),
+                "fd00::/8".parse().unwrap(),
+            ],
+            None,
+        );
+        let allowed: SocketAddr = "[::ffff:192.168.1.20]:5000".parse().unwrap();
+        let allowed_v6: SocketAddr = "[fd00::1]:5000".parse().unwrap();
+        let rejected: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+
+        assert_eq!(filter.check(&allowed, b"data"), Ok(&b"data"[..]));
+        assert_eq!(filter.check(&allowed_v6, b"data"), Ok(&b"data"[..]));
+        assert_eq!(
+            filter.check(&rejected, b"data"),
+            Err(FilterError::AddressNotAllowed("10.0.0.1".parse().unwrap()))
+        );
+        assert_eq!(filter.rejected_address(), 1);
+    }
+
+    #[test]
+    fn test_check_signature() {
+        let filter = DatagramFilter::new(vec![], Some("secret".to_string()));
+        let addr: SocketAddr = "127.0.0.1:5000".parse().unwrap();
+
+        let datagram = sign("secret", b"<contactinfo/>");
+        assert_eq!(filter.check(&addr, &datagram), Ok(&b"<contactinfo/>"[..]));
+
+        let datagram = sign("other", b"<contactinfo/>");
+        assert_eq!(
+            filter.check(&addr, &datagram),
+            Err(FilterError::InvalidSignature)
+        );
+        assert_eq!(
+            filter.check(&addr, b"<contactinfo/>"),
+            Err(FilterError::MissingSignature)
+        );
+        assert_eq!(filter.rejected_signature(), 2);
+    }
+}
@@ -0,0 +1,299 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::callbook::{
+    is_transient_request_error, CallbookError, CallbookProvider, CallbookRecord,
+};
+use async_trait::async_trait;
+use reqwest::Client;
+use serde::Deserialize;
+use std::fmt::Display;
+use std::time::Duration;
+use tokio::sync::Mutex;
+
+#[derive(Debug)]
+pub enum HamQTHError {
+    Request(reqwest::Error),
+    Parsing(serde_xml_rs::Error),
+    ApiError(String),
+}
+
+impl Display for HamQTHError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HamQTHError::Request(e) => {
+                write!(f, "Request error: {}", e)
+            }
+            HamQTHError::Parsing(e) => {
+                write!(f, "Parsing error: {}", e)
+            }
+            HamQTHError::ApiError(e) => {
+                write!(f, "API error: {}", e)
+            }
+        }
+    }
+}
+
+impl HamQTHError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            HamQTHError::Request(e) => is_transient_request_error(e),
+            HamQTHError::Parsing(_) => true,
+            HamQTHError::ApiError(_) => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, HamQTHError::ApiError(message) if message == NOT_FOUND)
+    }
+}
+
+impl From<reqwest::Error> for HamQTHError {
+    fn from(value: reqwest::Error) -> Self {
+        Self::Request(value)
+    }
+}
+
+impl From<serde_xml_rs::Error> for HamQTHError {
+    fn from(value: serde_xml_rs::Error) -> Self {
+        Self::Parsing(value)
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Search {
+    callsign: Option<String>,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+    grid: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct Session {
+    session_id: Option<String>,
+    error: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+struct ResponseBody {
+    search: Option<Search>,
+    session: Option<Session>,
+}
+
+const API_URL: &str = "https://www.hamqth.com/xml.php";
+const AGENT: &str = env!("CARGO_PKG_NAME");
+const SESSION_EXPIRED: &str = "Session does not exist or expired";
+const NOT_FOUND: &str = "Callsign not found";
+
+pub struct HamQTHClient {
+    client: Client,
+    username: String,
+    password: String,
+    session_id: Mutex<Option<String>>,
+}
+
+impl HamQTHClient {
+    pub fn new(username: &str, password: &str) -> Result<Self, HamQTHError> {
+        let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+
+        Ok(Self {
+            client,
+            username: username.to_string(),
+            password: password.to_string(),
+            session_id: Mutex::new(None),
+        })
+    }
+
+    pub async fn lookup_callsign(&self, callsign: &str) -> Result<CallbookRecord, HamQTHError> {
+        let session_id = self.session_id(None).await?;
+
+        let mut response = self.query(&session_id, callsign).await?;
+        if session_error(&response).as_deref() == Some(SESSION_EXPIRED) {
+            log::info!("HamQTH session is no longer valid, logging in again");
+            let session_id = self.session_id(Some(&session_id)).await?;
+            response = self.query(&session_id, callsign).await?;
+        }
+
+        match response.search {
+            Some(search) => Ok(CallbookRecord {
+                call: search
+                    .callsign
+                    .map(|call| call.to_uppercase())
+                    .unwrap_or(callsign.to_string()),
+                latitude: search.latitude,
+                longitude: search.longitude,
+                grid: search.grid.map(|grid| grid.to_uppercase()),
+            }),
+            None => Err(HamQTHError::ApiError(
+                session_error(&response).unwrap_or_default(),
+            )),
+        }
+    }
+
+    async fn session_id(&self, expired_id: Option<&str>) -> Result<String, HamQTHError> {
+        let mut session_id = self.session_id.lock().await;
+
+        match session_id.as_deref() {
+            Some(id) if Some(id) != expired_id => Ok(id.to_string()),
+            _ => {
+                *session_id = None;
+                let id = self.login().await?;
+                *session_id = Some(id.clone());
+                Ok(id)
+            }
+        }
+    }
+
+    async fn login(&self) -> Result<String, HamQTHError> {
+        log::debug!("Logging in to HamQTH as {}", self.username);
+
+        let response_body = self
+            .client
+            .get(API_URL)
+            .query(&[("u", self.username.as_str()), ("p", self.password.as_str())])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let response = parse_response(&response_body)?;
+        match response.session {
+            Some(Session {
+                session_id: Some(session_id),
+                ..
+            }) => Ok(session_id),
+            _ => Err(HamQTHError::ApiError(
+                session_error(&response).unwrap_or_default(),
+            )),
+        }
+    }
+
+    async fn query(&self, session_id: &str, callsign: &str) -> Result<ResponseBody, HamQTHError> {
+        let response_body = self
+            .client
+            .get(API_URL)
+            .query(&[("id", session_id), ("callsign", callsign), ("prg", AGENT)])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(parse_response(&response_body)?)
+    }
+}
+
+#[async_trait]
+impl CallbookProvider for HamQTHClient {
+    fn name(&self) -> &str {
+        "HamQTH"
+    }
+
+    async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+        Ok(self.lookup_callsign(callsign).await?)
+    }
+}
+
+fn session_error(response: &ResponseBody) -> Option<String> {
+    response
+        .session
+        .as_ref()
+        .and_then(|session| session.error.clone())
+}
+
+fn parse_response(payload: &str) -> Result<ResponseBody, serde_xml_rs::Error> {
+    serde_xml_rs::from_str(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hamqth::{parse_response, HamQTHError, ResponseBody, Search, Session};
+
+    #[test]
+    fn test_parse_response_login() {
+        let input = "<?xml version=\"1.0\"?>
+<HamQTH version=\"2.8\" xmlns=\"https://www.hamqth.com\">
+<session>
+<session_id>09b0ae90050be03c452ad235a1f2915ad684393c</session_id>
+</session>
+</HamQTH>";
+
+        let expected = ResponseBody {
+            search: None,
+            session: Some(Session {
+                session_id: Some("09b0ae90050be03c452ad235a1f2915ad684393c".to_string()),
+                error: None,
+            }),
+        };
+
+        let actual = parse_response(input).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_parse_response_ok() {
+        let input = "<?xml version=\"1.0\"?>
+<HamQTH version=\"2.8\" xmlns=\"https://www.hamqth.com\">
+<search>
+<callsign>is0gvh</callsign>
+<nick>Luca</nick>
+<grid>JM49</grid>
+<latitude>39.123456</latitude>
+<longitude>9.654321</longitude>
+</search>
+</HamQTH>";
+
+        let expected = ResponseBody {
+            search: Some(Search {
+                callsign: Some("is0gvh".to_string()),
+                latitude: Some(39.123456),
+                longitude: Some(9.654321),
+                grid: Some("JM49".to_string()),
+            }),
+            session: None,
+        };
+
+        let actual = parse_response(input).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn test_parse_response_error() {
+        let input = "<?xml version=\"1.0\"?>
+<HamQTH version=\"2.8\" xmlns=\"https://www.hamqth.com\">
+<session>
+<error>Callsign not found</error>
+</session>
+</HamQTH>";
+
+        let expected = ResponseBody {
+            search: None,
+            session: Some(Session {
+                session_id: None,
+                error: Some("Callsign not found".to_string()),
+            }),
+        };
+
+        let actual = parse_response(input).unwrap();
+
+        assert_eq!(actual, expected);
+        let error = actual.session.and_then(|session| session.error).unwrap();
+        assert!(HamQTHError::ApiError(error).is_not_found());
+    }
+}
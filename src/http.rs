@@ -14,24 +14,32 @@
  *
  */
 
-use crate::enricher::QSO;
+use crate::cache::CallsignCache;
+use crate::deadletter::DeadLetters;
+use crate::enricher::MapEvent;
+use crate::filter::DatagramFilter;
+use crate::maidenhead;
 use crate::models::Point;
+use crate::overrides::{LocationOverride, OverrideError, OverrideTable};
+use crate::receiver::{ContactEvent, ContactInfo};
+use actix_web::http::header;
 use actix_web::middleware::Logger;
 use actix_web::{
-    get, route, rt, web, App, Error, HttpRequest, HttpResponse, HttpServer, Responder,
+    delete, get, post, put, route, rt, web, App, Error, HttpRequest, HttpResponse, HttpServer,
+    Responder,
 };
 use actix_web_rust_embed_responder::{EmbedResponse, IntoResponse, WebEmbedableFile};
 use actix_ws::AggregatedMessage;
 use async_broadcast::InactiveReceiver;
 use rust_embed_for_web::RustEmbed;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 #[cfg(debug_assertions)]
 use rust_embed_for_web::DynamicFile;
 #[cfg(not(debug_assertions))]
 use rust_embed_for_web::EmbeddedFile;
 
-use serde::{Deserialize, Serialize};
-
 #[derive(RustEmbed)]
 #[folder = "assets/"]
 struct Assets;
@@ -42,6 +50,55 @@ type FileType = DynamicFile;
 #[cfg(not(debug_assertions))]
 type FileType = EmbeddedFile;
 
+#[derive(Debug, Clone)]
+pub struct ApiToken(pub Option<String>);
+
+impl ApiToken {
+    fn authorize(&self, req: &HttpRequest) -> bool {
+        let expected = match &self.0 {
+            Some(token) => token,
+            None => return false,
+        };
+
+        let provided = req
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|value| value.trim());
+
+        match provided {
+            Some(provided) => constant_time_eq(provided.as_bytes(), expected.as_bytes()),
+            None => false,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct HttpContext {
+    pub home_point: Point,
+    pub api_token: ApiToken,
+    pub contact_event_sender: async_channel::Sender<ContactEvent>,
+    pub map_event_receiver: InactiveReceiver<MapEvent>,
+    pub datagram_filter: Arc<DatagramFilter>,
+    pub callsign_cache: Arc<CallsignCache>,
+    pub dead_letters: Arc<DeadLetters>,
+    pub override_table: Arc<OverrideTable>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct ErrorResponse {
+    error: String,
+}
+
+impl ErrorResponse {
+    fn new(error: &str) -> Self {
+        Self {
+            error: error.to_string(),
+        }
+    }
+}
+
 #[route("/assets/{path:.*}", method = "GET", method = "HEAD")]
 async fn serve_assets(path: web::Path<String>) -> EmbedResponse<WebEmbedableFile<FileType>> {
     let path = if path.is_empty() {
@@ -55,17 +112,320 @@ async fn serve_assets(path: web::Path<String>) -> EmbedResponse<WebEmbedableFile
 
 #[get("/api/public/v1/points/home")]
 async fn home_point_service(home_point: web::Data<Point>) -> impl Responder {
-    #[derive(Debug, Serialize, Deserialize, PartialEq)]
-    struct ResponseBody {
-        latitude: f64,
-        longitude: f64,
-    }
-
     HttpResponse::Ok()
         .content_type("application/json")
         .json(&home_point)
 }
 
+#[derive(Debug, Deserialize)]
+struct SubmitQSORequest {
+    #[serde(flatten)]
+    contact_info: ContactInfo,
+    latitude: Option<f64>,
+    longitude: Option<f64>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct SubmitQSOResponse {
+    id: Option<String>,
+    enriched: bool,
+}
+
+#[post("/api/v1/qsos")]
+async fn submit_qso_service(
+    req: HttpRequest,
+    body: web::Json<SubmitQSORequest>,
+    api_token: web::Data<ApiToken>,
+    contact_event_sender: web::Data<async_channel::Sender<ContactEvent>>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    let SubmitQSORequest {
+        mut contact_info,
+        latitude,
+        longitude,
+    } = body.into_inner();
+
+    contact_info.call = contact_info.call.trim().to_uppercase();
+    if contact_info.call.is_empty() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Empty callsign"));
+    }
+    if contact_info.id.is_none() {
+        contact_info.id = contact_info.synthetic_id();
+    }
+
+    match (latitude, longitude) {
+        (Some(latitude), Some(longitude)) => {
+            contact_info.location = Some(Point {
+                latitude,
+                longitude,
+            });
+        }
+        (None, None) => {}
+        _ => {
+            return HttpResponse::BadRequest().json(ErrorResponse::new(
+                "Latitude and longitude must be provided together",
+            ))
+        }
+    }
+    if let Some(location) = contact_info.location {
+        if !(-90.0..=90.0).contains(&location.latitude)
+            || !(-180.0..=180.0).contains(&location.longitude)
+        {
+            return HttpResponse::BadRequest().json(ErrorResponse::new("Coordinates out of range"));
+        }
+    }
+
+    let id = contact_info.id.clone();
+    log::info!("Received contact info from API: {}", &contact_info);
+
+    let enriched = contact_info.location.is_none()
+        && !contact_info
+            .grid
+            .as_deref()
+            .is_some_and(maidenhead::is_locator);
+    match contact_event_sender
+        .send(ContactEvent::Insert(contact_info))
+        .await
+    {
+        Ok(_) => HttpResponse::Accepted().json(SubmitQSOResponse { id, enriched }),
+        Err(e) => {
+            log::warn!("Failed to send contact event: {}", e);
+            HttpResponse::ServiceUnavailable().json(ErrorResponse::new("Enricher unavailable"))
+        }
+    }
+}
+
+fn unauthorized() -> HttpResponse {
+    HttpResponse::Unauthorized()
+        .insert_header((header::WWW_AUTHENTICATE, "Bearer"))
+        .json(ErrorResponse::new("Invalid or missing bearer token"))
+}
+
+#[get("/api/v1/deadletters")]
+async fn dead_letters_service(
+    req: HttpRequest,
+    api_token: web::Data<ApiToken>,
+    dead_letters: web::Data<DeadLetters>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    HttpResponse::Ok().json(dead_letters.list())
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RetryDeadLetterRequest {
+    call: Option<String>,
+}
+
+#[post("/api/v1/deadletters/{id}/retry")]
+async fn retry_dead_letter_service(
+    req: HttpRequest,
+    path: web::Path<u64>,
+    body: Option<web::Json<RetryDeadLetterRequest>>,
+    api_token: web::Data<ApiToken>,
+    dead_letters: web::Data<DeadLetters>,
+    contact_event_sender: web::Data<async_channel::Sender<ContactEvent>>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    let call = body
+        .map(|body| body.into_inner())
+        .unwrap_or_default()
+        .call
+        .map(|call| call.trim().to_uppercase());
+    if call.as_deref().is_some_and(str::is_empty) {
+        return HttpResponse::BadRequest().json(ErrorResponse::new("Empty callsign"));
+    }
+
+    let dead_letter = match dead_letters.take(path.into_inner()) {
+        Some(dead_letter) => dead_letter,
+        None => return HttpResponse::NotFound().json(ErrorResponse::new("Dead letter not found")),
+    };
+
+    let mut contact_info = dead_letter.contact;
+    if let Some(call) = call {
+        contact_info.call = call;
+    }
+    log::info!("Retrying dead letter {}: {}", dead_letter.id, &contact_info);
+
+    let id = contact_info.id.clone();
+    let contact_event = match &id {
+        Some(_) => ContactEvent::Replace(contact_info),
+        None => ContactEvent::Insert(contact_info),
+    };
+
+    match contact_event_sender.send(contact_event).await {
+        Ok(_) => HttpResponse::Accepted().json(SubmitQSOResponse { id, enriched: true }),
+        Err(e) => {
+            log::warn!("Failed to send contact event: {}", e);
+            HttpResponse::ServiceUnavailable().json(ErrorResponse::new("Enricher unavailable"))
+        }
+    }
+}
+
+#[delete("/api/v1/deadletters/{id}")]
+async fn delete_dead_letter_service(
+    req: HttpRequest,
+    path: web::Path<u64>,
+    api_token: web::Data<ApiToken>,
+    dead_letters: web::Data<DeadLetters>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    match dead_letters.take(path.into_inner()) {
+        Some(_) => HttpResponse::NoContent().finish(),
+        None => HttpResponse::NotFound().json(ErrorResponse::new("Dead letter not found")),
+    }
+}
+
+fn override_error(e: OverrideError) -> HttpResponse {
+    match e {
+        OverrideError::InvalidEntry(_) | OverrideError::InvalidPattern(_) => {
+            HttpResponse::BadRequest().json(ErrorResponse::new(&e.to_string()))
+        }
+        _ => {
+            log::warn!("Unable to update overrides: {}", e);
+            HttpResponse::InternalServerError().json(ErrorResponse::new(&e.to_string()))
+        }
+    }
+}
+
+#[get("/api/v1/overrides")]
+async fn overrides_service(
+    req: HttpRequest,
+    api_token: web::Data<ApiToken>,
+    override_table: web::Data<OverrideTable>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    HttpResponse::Ok().json(override_table.list())
+}
+
+#[put("/api/v1/overrides")]
+async fn put_override_service(
+    req: HttpRequest,
+    body: web::Json<LocationOverride>,
+    api_token: web::Data<ApiToken>,
+    override_table: web::Data<OverrideTable>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    match override_table.upsert(body.into_inner()) {
+        Ok(entry) => {
+            log::info!("Override {} updated", entry);
+            HttpResponse::Ok().json(entry)
+        }
+        Err(e) => override_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct DeleteOverrideQuery {
+    call: Option<String>,
+    pattern: Option<String>,
+}
+
+#[delete("/api/v1/overrides")]
+async fn delete_override_service(
+    req: HttpRequest,
+    query: web::Query<DeleteOverrideQuery>,
+    api_token: web::Data<ApiToken>,
+    override_table: web::Data<OverrideTable>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    if query.call.is_some() == query.pattern.is_some() {
+        return HttpResponse::BadRequest().json(ErrorResponse::new(
+            "Exactly one of call and pattern is required",
+        ));
+    }
+
+    match override_table.remove(query.call.as_deref(), query.pattern.as_deref()) {
+        Ok(true) => HttpResponse::NoContent().finish(),
+        Ok(false) => HttpResponse::NotFound().json(ErrorResponse::new("Override not found")),
+        Err(e) => override_error(e),
+    }
+}
+
+#[post("/api/v1/overrides/reload")]
+async fn reload_overrides_service(
+    req: HttpRequest,
+    api_token: web::Data<ApiToken>,
+    override_table: web::Data<OverrideTable>,
+) -> impl Responder {
+    if !api_token.authorize(&req) {
+        return unauthorized();
+    }
+
+    #[derive(Debug, Serialize)]
+    struct ResponseBody {
+        overrides: usize,
+    }
+
+    match override_table.reload() {
+        Ok(overrides) => HttpResponse::Ok().json(ResponseBody { overrides }),
+        Err(e) => {
+            log::warn!("Unable to reload overrides: {}", e);
+            HttpResponse::UnprocessableEntity().json(ErrorResponse::new(&e.to_string()))
+        }
+    }
+}
+
+#[get("/api/public/v1/stats")]
+async fn stats_service(
+    datagram_filter: web::Data<DatagramFilter>,
+    callsign_cache: web::Data<CallsignCache>,
+    dead_letters: web::Data<DeadLetters>,
+) -> impl Responder {
+    #[derive(Debug, Serialize)]
+    struct ReceiverStats {
+        rejected_address: u64,
+        rejected_signature: u64,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct CacheStats {
+        hits: u64,
+        misses: u64,
+        entries: usize,
+    }
+
+    #[derive(Debug, Serialize)]
+    struct ResponseBody {
+        receiver: ReceiverStats,
+        cache: CacheStats,
+        dead_letters: usize,
+    }
+
+    HttpResponse::Ok().json(ResponseBody {
+        receiver: ReceiverStats {
+            rejected_address: datagram_filter.rejected_address(),
+            rejected_signature: datagram_filter.rejected_signature(),
+        },
+        cache: CacheStats {
+            hits: callsign_cache.hits(),
+            misses: callsign_cache.misses(),
+            entries: callsign_cache.len(),
+        },
+        dead_letters: dead_letters.len(),
+    })
+}
+
 #[get("/health")]
 async fn health() -> impl Responder {
     HttpResponse::NoContent()
@@ -74,7 +434,7 @@ async fn health() -> impl Responder {
 async fn ws(
     req: HttpRequest,
     stream: web::Payload,
-    qso_receiver: web::Data<InactiveReceiver<QSO>>,
+    map_event_receiver: web::Data<InactiveReceiver<MapEvent>>,
 ) -> Result<HttpResponse, Error> {
     let (res, mut session, stream) = actix_ws::handle(&req, stream)?;
 
@@ -87,21 +447,17 @@ async fn ws(
     rt::spawn(async move {
         // receive messages from websocket
         while let Some(msg) = rx_stream.recv().await {
-            match msg {
-                Ok(AggregatedMessage::Ping(msg)) => {
-                    rx_session.pong(&msg).await.unwrap();
-                }
-
-                _ => {}
+            if let Ok(AggregatedMessage::Ping(msg)) = msg {
+                rx_session.pong(&msg).await.unwrap();
             }
         }
     });
 
     rt::spawn(async move {
-        let qso_receiver = qso_receiver.get_ref().clone();
-        let mut qso_receiver = qso_receiver.activate();
-        while let Some(qso) = qso_receiver.recv().await.ok() {
-            let data = serde_json::to_string(&qso).unwrap();
+        let map_event_receiver = map_event_receiver.get_ref().clone();
+        let mut map_event_receiver = map_event_receiver.activate();
+        while let Ok(map_event) = map_event_receiver.recv().await {
+            let data = serde_json::to_string(&map_event).unwrap();
             session.text(data).await.unwrap();
         }
     });
@@ -112,17 +468,35 @@ async fn ws(
 pub async fn run_http_server(
     http_host: &str,
     http_port: u16,
-    home_point: Point,
-    qso_receiver: InactiveReceiver<QSO>,
+    context: HttpContext,
 ) -> std::io::Result<()> {
+    if context.api_token.0.is_none() {
+        log::warn!("API token not configured, authenticated endpoints are disabled");
+    }
+
     HttpServer::new(move || {
         App::new()
-            .app_data(web::Data::new(qso_receiver.clone()))
-            .app_data(web::Data::new(home_point))
+            .app_data(web::Data::new(context.map_event_receiver.clone()))
+            .app_data(web::Data::new(context.contact_event_sender.clone()))
+            .app_data(web::Data::new(context.api_token.clone()))
+            .app_data(web::Data::new(context.home_point))
+            .app_data(web::Data::from(context.datagram_filter.clone()))
+            .app_data(web::Data::from(context.callsign_cache.clone()))
+            .app_data(web::Data::from(context.dead_letters.clone()))
+            .app_data(web::Data::from(context.override_table.clone()))
             .wrap(Logger::default())
             .service(web::redirect("/", "/assets/"))
             .service(serve_assets)
             .service(home_point_service)
+            .service(submit_qso_service)
+            .service(dead_letters_service)
+            .service(retry_dead_letter_service)
+            .service(delete_dead_letter_service)
+            .service(overrides_service)
+            .service(put_override_service)
+            .service(delete_override_service)
+            .service(reload_overrides_service)
+            .service(stats_service)
             .service(health)
             .service(web::resource("/api/public/v1/map/ws").route(web::get().to(ws)))
     })
@@ -130,3 +504,239 @@ pub async fn run_http_server(
     .run()
     .await
 }
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::deadletter::DeadLetters;
+    use crate::dedup::{Deduplicator, DupePolicy};
+    use crate::http::{
+        dead_letters_service, delete_dead_letter_service, delete_override_service,
+        overrides_service, put_override_service, retry_dead_letter_service, submit_qso_service,
+        ApiToken,
+    };
+    use crate::models::Point;
+    use crate::overrides::OverrideTable;
+    use crate::receiver::{ContactEvent, ContactInfo};
+    use actix_web::http::StatusCode;
+    use actix_web::{test, web, App};
+    use chrono::Utc;
+    use std::time::{Duration, Instant};
+
+    #[actix_web::test]
+    async fn test_submit_qso_service() {
+        let (contact_event_sender, contact_event_receiver) =
+            async_channel::unbounded::<ContactEvent>();
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ApiToken(Some("secret".to_string()))))
+                .app_data(web::Data::new(contact_event_sender))
+                .service(submit_qso_service),
+        )
+        .await;
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .set_json(serde_json::json!({"call": "W1AW", "band": "20"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"call": "w1aw", "band": "20"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        match contact_event_receiver.recv().await.unwrap() {
+            ContactEvent::Insert(contact_info) => assert_eq!(contact_info.call, "W1AW"),
+            other => panic!("Unexpected event: {}", other),
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({
+                "call": "K1ABC", "band": "40", "latitude": 41.5, "longitude": -72.75
+            }))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["enriched"], false);
+        let contact_event = contact_event_receiver.recv().await.unwrap();
+        match &contact_event {
+            ContactEvent::Insert(contact_info) => {
+                assert_eq!(contact_info.call, "K1ABC");
+                assert_eq!(
+                    contact_info.location,
+                    Some(Point {
+                        latitude: 41.5,
+                        longitude: -72.75
+                    })
+                );
+            }
+            other => panic!("Unexpected event: {}", other),
+        }
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({
+                "call": "K1ABC", "band": "40", "latitude": 41.5, "longitude": -72.75
+            }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        let resent_contact_event = contact_event_receiver.recv().await.unwrap();
+
+        let mut deduplicator = Deduplicator::new(DupePolicy::Show, Duration::from_secs(60));
+        let now = Instant::now();
+        assert!(deduplicator.process(contact_event, now).is_some());
+        assert!(deduplicator.process(resent_contact_event, now).is_none());
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"call": "K2ABC", "band": "40", "grid": "FN31"}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["enriched"], false);
+        contact_event_receiver.recv().await.unwrap();
+
+        let req = test::TestRequest::post()
+            .uri("/api/v1/qsos")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"call": "K1ABC", "band": "40", "latitude": 41.5}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    #[actix_web::test]
+    async fn test_dead_letter_services() {
+        let (contact_event_sender, contact_event_receiver) =
+            async_channel::unbounded::<ContactEvent>();
+        let dead_letters = DeadLetters::default();
+        let contact_info: ContactInfo = serde_json::from_value(serde_json::json!({
+            "id": "42", "call": "IS0XXX", "band": "20"
+        }))
+        .unwrap();
+        let first = dead_letters.push(contact_info.clone(), "Not found".into(), Utc::now());
+        let dead_letters = web::Data::new(dead_letters);
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ApiToken(Some("secret".to_string()))))
+                .app_data(web::Data::new(contact_event_sender))
+                .app_data(dead_letters.clone())
+                .service(dead_letters_service)
+                .service(retry_dead_letter_service)
+                .service(delete_dead_letter_service),
+        )
+        .await;
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/deadletters")
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/deadletters")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body[0]["id"], first);
+        assert_eq!(body[0]["contact"]["call"], "IS0XXX");
+
+        let req = test::TestRequest::post()
+            .uri(&format!("/api/v1/deadletters/{}/retry", first))
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"call": "is0xxy"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::ACCEPTED);
+        match contact_event_receiver.recv().await.unwrap() {
+            ContactEvent::Replace(contact_info) => assert_eq!(contact_info.call, "IS0XXY"),
+            other => panic!("Unexpected event: {}", other),
+        }
+        assert_eq!(dead_letters.len(), 0);
+
+        let second = dead_letters.push(contact_info, "Not found".into(), Utc::now());
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/deadletters/{}", second))
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let req = test::TestRequest::delete()
+            .uri(&format!("/api/v1/deadletters/{}", second))
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[actix_web::test]
+    async fn test_override_services() {
+        let override_table = web::Data::new(OverrideTable::load(None).unwrap());
+
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(ApiToken(Some("secret".to_string()))))
+                .app_data(override_table.clone())
+                .service(overrides_service)
+                .service(put_override_service)
+                .service(delete_override_service),
+        )
+        .await;
+
+        let req = test::TestRequest::put()
+            .uri("/api/v1/overrides")
+            .set_json(serde_json::json!({"call": "K1ABC", "grid": "FN31"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let req = test::TestRequest::put()
+            .uri("/api/v1/overrides")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"call": "k1abc", "grid": "fn31", "note": "Club"}))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body["call"], "K1ABC");
+        assert_eq!(body["grid"], "FN31");
+
+        let req = test::TestRequest::put()
+            .uri("/api/v1/overrides")
+            .insert_header(("Authorization", "Bearer secret"))
+            .set_json(serde_json::json!({"pattern": "K1(", "grid": "FN31"}))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let req = test::TestRequest::get()
+            .uri("/api/v1/overrides")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let body: serde_json::Value = test::call_and_read_body_json(&app, req).await;
+        assert_eq!(body.as_array().unwrap().len(), 1);
+
+        let req = test::TestRequest::delete()
+            .uri("/api/v1/overrides?call=K1ABC")
+            .insert_header(("Authorization", "Bearer secret"))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_eq!(override_table.len(), 0);
+    }
+}
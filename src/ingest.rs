@@ -0,0 +1,306 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::receiver::{ContactEvent, ContactInfo};
+use async_channel::Sender;
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+#[cfg(unix)]
+use std::path::Path;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+
+const MAX_LINE_LENGTH: usize = 65536;
+
+#[derive(Debug)]
+pub enum IngestError {
+    Socket(std::io::Error),
+}
+
+impl Display for IngestError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IngestError::Socket(e) => {
+                write!(f, "Socket error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for IngestError {
+    fn from(value: std::io::Error) -> Self {
+        Self::Socket(value)
+    }
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+struct Acknowledgement {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl Acknowledgement {
+    fn ok(id: Option<String>) -> Self {
+        Self {
+            status: "ok",
+            id,
+            error: None,
+        }
+    }
+
+    fn error(error: String) -> Self {
+        Self {
+            status: "error",
+            id: None,
+            error: Some(error),
+        }
+    }
+}
+
+pub async fn run_tcp_ingest(
+    address: &str,
+    contact_event_sender: Sender<ContactEvent>,
+) -> Result<(), IngestError> {
+    let listener = TcpListener::bind(address).await?;
+
+    loop {
+        let (stream, addr) = listener.accept().await?;
+        log::debug!("Ingest connection from {}", addr);
+
+        let contact_event_sender = contact_event_sender.clone();
+        tokio::spawn(async move {
+            handle_connection(stream, &addr.to_string(), contact_event_sender).await;
+        });
+    }
+}
+
+#[cfg(unix)]
+pub async fn run_unix_ingest(
+    path: &Path,
+    contact_event_sender: Sender<ContactEvent>,
+) -> Result<(), IngestError> {
+    use std::os::unix::fs::FileTypeExt;
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if metadata.file_type().is_socket() {
+            log::debug!("Removing stale socket {}", path.display());
+            std::fs::remove_file(path)?;
+        }
+    }
+
+    let listener = UnixListener::bind(path)?;
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        log::debug!("Ingest connection on {}", path.display());
+
+        let contact_event_sender = contact_event_sender.clone();
+        let peer = path.display().to_string();
+        tokio::spawn(async move {
+            handle_connection(stream, &peer, contact_event_sender).await;
+        });
+    }
+}
+
+async fn handle_connection<S: AsyncRead + AsyncWrite>(
+    stream: S,
+    peer: &str,
+    contact_event_sender: Sender<ContactEvent>,
+) {
+    let (reader, mut writer) = tokio::io::split(stream);
+    let mut reader = BufReader::new(reader);
+    let mut buf = Vec::new();
+
+    loop {
+        buf.clear();
+        match (&mut reader)
+            .take(MAX_LINE_LENGTH as u64 + 1)
+            .read_until(b'\n', &mut buf)
+            .await
+        {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                log::warn!("Error reading from ingest connection {}: {}", peer, e);
+                break;
+            }
+        }
+
+        if buf.len() > MAX_LINE_LENGTH && buf.last() != Some(&b'\n') {
+            log::warn!(
+                "Line from ingest connection {} exceeds {} bytes, closing",
+                peer,
+                MAX_LINE_LENGTH
+            );
+            let acknowledgement =
+                Acknowledgement::error(format!("Line longer than {} bytes", MAX_LINE_LENGTH));
+            let _ = send_acknowledgement(&mut writer, &acknowledgement).await;
+            break;
+        }
+
+        let line = match std::str::from_utf8(&buf) {
+            Ok(line) => line.trim(),
+            Err(e) => {
+                log::warn!("Invalid UTF-8 from ingest connection {}: {}", peer, e);
+                if send_acknowledgement(&mut writer, &Acknowledgement::error(e.to_string()))
+                    .await
+                    .is_err()
+                {
+                    break;
+                }
+                continue;
+            }
+        };
+
+        if line.is_empty() {
+            continue;
+        }
+
+        let acknowledgement = match parse_line(line) {
+            Ok(contact_info) => {
+                log::info!("Received contact info from {}: {}", peer, &contact_info);
+                let id = contact_info.id.clone();
+                match contact_event_sender
+                    .send(ContactEvent::Insert(contact_info))
+                    .await
+                {
+                    Ok(_) => Acknowledgement::ok(id),
+                    Err(e) => Acknowledgement::error(e.to_string()),
+                }
+            }
+            Err(e) => {
+                log::warn!("Failed to parse contact info from {}: {}", peer, e);
+                Acknowledgement::error(e.to_string())
+            }
+        };
+
+        if let Err(e) = send_acknowledgement(&mut writer, &acknowledgement).await {
+            log::warn!("Error writing to ingest connection {}: {}", peer, e);
+            break;
+        }
+    }
+
+    log::debug!("Ingest connection {} closed", peer);
+}
+
+async fn send_acknowledgement<W: AsyncWrite + Unpin>(
+    writer: &mut W,
+    acknowledgement: &Acknowledgement,
+) -> std::io::Result<()> {
+    let mut response = serde_json::to_string(acknowledgement).unwrap();
+    response.push('\n');
+    writer.write_all(response.as_bytes()).await
+}
+
+fn parse_line(line: &str) -> Result<ContactInfo, serde_json::Error> {
+    let mut contact_info: ContactInfo = serde_json::from_str(line)?;
+    contact_info.call = contact_info.call.trim().to_uppercase();
+    if contact_info.call.is_empty() {
+        return Err(serde::de::Error::custom("Empty callsign"));
+    }
+    if contact_info.id.is_none() {
+        contact_info.id = contact_info.synthetic_id();
+    }
+
+    Ok(contact_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ingest::{handle_connection, parse_line, MAX_LINE_LENGTH};
+    use crate::receiver::ContactEvent;
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    #[test]
+    fn test_parse_line() {
+        let actual = parse_line(
+            "{\"call\":\"w1aw\",\"band\":\"20\",\"mode\":\"CW\",\"timestamp\":\"2024-10-24T09:00:00Z\"}",
+        )
+        .unwrap();
+
+        assert_eq!(actual.call, "W1AW");
+        assert_eq!(actual.mode, Some("CW".to_string()));
+        assert!(!actual.duplicate);
+        assert_eq!(actual.id, Some("W1AW-20241024090000".to_string()));
+
+        assert!(parse_line("{\"band\":\"20\"}").is_err());
+        assert_eq!(
+            parse_line("{\"call\":\"  \",\"band\":\"20\"}")
+                .unwrap_err()
+                .to_string(),
+            "Empty callsign"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (sender, receiver) = async_channel::unbounded();
+
+        tokio::spawn(async move {
+            handle_connection(server, "test", sender).await;
+        });
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(reader).lines();
+
+        writer
+            .write_all(b"{\"id\":\"1\",\"call\":\"W1AW\",\"band\":\"20\"}\n")
+            .await
+            .unwrap();
+        assert_eq!(
+            lines.next_line().await.unwrap().unwrap(),
+            "{\"status\":\"ok\",\"id\":\"1\"}"
+        );
+        match receiver.recv().await.unwrap() {
+            ContactEvent::Insert(contact_info) => assert_eq!(contact_info.call, "W1AW"),
+            other => panic!("Unexpected event: {}", other),
+        }
+
+        writer.write_all(b"not json\n").await.unwrap();
+        let response = lines.next_line().await.unwrap().unwrap();
+        assert!(response.starts_with("{\"status\":\"error\""));
+    }
+
+    #[tokio::test]
+    async fn test_handle_connection_line_too_long() {
+        let (client, server) = tokio::io::duplex(1024);
+        let (sender, receiver) = async_channel::unbounded();
+
+        tokio::spawn(async move {
+            handle_connection(server, "test", sender).await;
+        });
+
+        let (reader, mut writer) = tokio::io::split(client);
+        let mut lines = BufReader::new(reader).lines();
+
+        tokio::spawn(async move {
+            let _ = writer.write_all(&vec![b'x'; MAX_LINE_LENGTH * 2]).await;
+        });
+
+        let response = lines.next_line().await.unwrap().unwrap();
+        assert!(response.starts_with("{\"status\":\"error\""));
+        assert!(response.contains("Line longer than"));
+        assert_eq!(lines.next_line().await.unwrap(), None);
+        assert!(receiver.is_empty());
+    }
+}
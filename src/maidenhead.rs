@@ -0,0 +1,186 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::models::Point;
+use std::fmt::{Display, Formatter};
+
+const STEPS: [(u8, f64, f64); 4] = [
+    (b'A', 20.0, 10.0),
+    (b'0', 2.0, 1.0),
+    (b'A', 2.0 / 24.0, 1.0 / 24.0),
+    (b'0', 2.0 / 240.0, 1.0 / 240.0),
+];
+const LIMITS: [u8; 4] = [18, 10, 24, 10];
+
+#[derive(Debug, PartialEq)]
+pub enum MaidenheadError {
+    InvalidLength(usize),
+    InvalidCharacter(char),
+    OutOfRange,
+}
+
+impl Display for MaidenheadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MaidenheadError::InvalidLength(e) => {
+                write!(f, "Invalid locator length: {}", e)
+            }
+            MaidenheadError::InvalidCharacter(e) => {
+                write!(f, "Invalid locator character: {}", e)
+            }
+            MaidenheadError::OutOfRange => {
+                write!(f, "Coordinates out of range")
+            }
+        }
+    }
+}
+
+pub fn decode(locator: &str) -> Result<Point, MaidenheadError> {
+    let locator = locator.trim().to_ascii_uppercase();
+    let bytes = locator.as_bytes();
+    if !matches!(bytes.len(), 2 | 4 | 6 | 8) {
+        return Err(MaidenheadError::InvalidLength(bytes.len()));
+    }
+
+    let mut longitude = -180.0;
+    let mut latitude = -90.0;
+    let mut size = (0.0, 0.0);
+
+    for (index, pair) in bytes.chunks(2).enumerate() {
+        let (base, longitude_size, latitude_size) = STEPS[index];
+        let longitude_index = pair_index(pair[0], base, LIMITS[index])?;
+        let latitude_index = pair_index(pair[1], base, LIMITS[index])?;
+
+        longitude += longitude_index as f64 * longitude_size;
+        latitude += latitude_index as f64 * latitude_size;
+        size = (longitude_size, latitude_size);
+    }
+
+    Ok(Point {
+        latitude: latitude + size.1 / 2.0,
+        longitude: longitude + size.0 / 2.0,
+    })
+}
+
+pub fn encode(point: &Point, length: usize) -> Result<String, MaidenheadError> {
+    if !matches!(length, 2 | 4 | 6 | 8) {
+        return Err(MaidenheadError::InvalidLength(length));
+    }
+    if !(-90.0..=90.0).contains(&point.latitude) || !(-180.0..=180.0).contains(&point.longitude) {
+        return Err(MaidenheadError::OutOfRange);
+    }
+
+    let mut longitude = (point.longitude + 180.0).min(360.0 - f64::EPSILON * 512.0);
+    let mut latitude = (point.latitude + 90.0).min(180.0 - f64::EPSILON * 256.0);
+    let mut locator = String::with_capacity(length);
+
+    for (base, longitude_size, latitude_size) in STEPS.iter().take(length / 2) {
+        let longitude_index = (longitude / longitude_size).floor();
+        let latitude_index = (latitude / latitude_size).floor();
+
+        locator.push((base + longitude_index as u8) as char);
+        locator.push((base + latitude_index as u8) as char);
+
+        longitude -= longitude_index * longitude_size;
+        latitude -= latitude_index * latitude_size;
+    }
+
+    Ok(locator)
+}
+
+pub fn is_locator(value: &str) -> bool {
+    let value = value.trim();
+    value.len() >= 4 && decode(value).is_ok()
+}
+
+fn pair_index(value: u8, base: u8, limit: u8) -> Result<u8, MaidenheadError> {
+    match value.checked_sub(base) {
+        Some(index) if index < limit => Ok(index),
+        _ => Err(MaidenheadError::InvalidCharacter(value as char)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::maidenhead::{decode, encode, is_locator, MaidenheadError};
+    use crate::models::Point;
+
+    fn assert_point(actual: Point, latitude: f64, longitude: f64) {
+        assert!((actual.latitude - latitude).abs() < 1e-9, "{:?}", actual);
+        assert!((actual.longitude - longitude).abs() < 1e-9, "{:?}", actual);
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_point(decode("JM").unwrap(), 35.0, 10.0);
+        assert_point(decode("JM49").unwrap(), 39.5, 9.0);
+        assert_point(decode("fn31pr").unwrap(), 41.729166666, -72.708333333);
+        assert_point(decode("JM49NA55").unwrap(), 39.022916666, 9.129166666);
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        assert_eq!(decode("JM4"), Err(MaidenheadError::InvalidLength(3)));
+        assert_eq!(decode("SM49"), Err(MaidenheadError::InvalidCharacter('S')));
+        assert_eq!(
+            decode("JM49ZA"),
+            Err(MaidenheadError::InvalidCharacter('Z'))
+        );
+        assert_eq!(decode("JMA9"), Err(MaidenheadError::InvalidCharacter('A')));
+    }
+
+    #[test]
+    fn test_encode() {
+        let point = Point {
+            latitude: 41.714775,
+            longitude: -72.727260,
+        };
+
+        assert_eq!(encode(&point, 2).unwrap(), "FN");
+        assert_eq!(encode(&point, 4).unwrap(), "FN31");
+        assert_eq!(encode(&point, 6).unwrap(), "FN31PR");
+        assert_eq!(encode(&point, 8).unwrap(), "FN31PR21");
+        assert_eq!(encode(&point, 5), Err(MaidenheadError::InvalidLength(5)));
+
+        let point = Point {
+            latitude: 90.0,
+            longitude: 180.0,
+        };
+        assert_eq!(encode(&point, 6).unwrap(), "RR99XX");
+
+        let point = Point {
+            latitude: 91.0,
+            longitude: 0.0,
+        };
+        assert_eq!(encode(&point, 6), Err(MaidenheadError::OutOfRange));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let point = decode("JM49NA55").unwrap();
+        assert_eq!(encode(&point, 8).unwrap(), "JM49NA55");
+    }
+
+    #[test]
+    fn test_is_locator() {
+        assert!(is_locator("JN54"));
+        assert!(is_locator("jn54mk"));
+        assert!(!is_locator("JN"));
+        assert!(!is_locator("599"));
+        assert!(!is_locator("CA"));
+        assert!(!is_locator("TX01"));
+    }
+}
@@ -14,20 +14,50 @@
  *
  */
 
+mod adif;
+mod cabrillo;
+mod cache;
+mod callbook;
+mod callsign;
 mod config;
+mod deadletter;
+mod dedup;
+mod dxcc;
 mod enricher;
+mod filter;
+mod hamqth;
 mod http;
+mod ingest;
 mod logging;
+mod maidenhead;
 mod models;
+mod overrides;
 mod qrzcom;
 mod receiver;
+mod throttle;
+mod watcher;
+mod wsjtx;
 
+use crate::cache::CallsignCache;
+use crate::callbook::{CallbookChain, CallbookKind, CallbookProvider};
 use crate::config::Config;
-use crate::enricher::QSO;
+use crate::deadletter::DeadLetters;
+use crate::dedup::Deduplicator;
+use crate::dxcc::DxccResolver;
+use crate::enricher::{EnricherContext, MapEvent};
+use crate::filter::DatagramFilter;
+use crate::hamqth::HamQTHClient;
+use crate::http::{ApiToken, HttpContext};
 use crate::models::Point;
-use crate::receiver::ContactInfo;
+use crate::overrides::OverrideTable;
+use crate::qrzcom::QRZComClient;
+use crate::receiver::{ContactEvent, ReceiverProtocol, Source};
+use crate::throttle::{ThrottleSettings, ThrottledProvider};
+use crate::watcher::LogFormat;
 use async_broadcast::InactiveReceiver;
 use clap::Parser;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
@@ -35,42 +65,261 @@ async fn main() -> std::io::Result<()> {
 
     logging::configure(&configuration.log_level);
 
-    let (contact_info_sender, contact_info_receiver): (
-        async_channel::Sender<ContactInfo>,
-        async_channel::Receiver<ContactInfo>,
+    let (contact_event_sender, contact_event_receiver): (
+        async_channel::Sender<ContactEvent>,
+        async_channel::Receiver<ContactEvent>,
     ) = async_channel::unbounded();
-    let (qso_sender, qso_receiver): (async_broadcast::Sender<QSO>, async_broadcast::Receiver<QSO>) =
-        async_broadcast::broadcast(3);
-    let qso_receiver: InactiveReceiver<QSO> = qso_receiver.deactivate();
-
-    let bind_host = configuration.bind_host;
-    let bind_port = configuration.bind_port;
-    let _task_receiver = tokio::spawn(async move {
-        receiver::run_receiver(&bind_host, bind_port, contact_info_sender).await
-    });
+    let (unique_contact_event_sender, unique_contact_event_receiver): (
+        async_channel::Sender<ContactEvent>,
+        async_channel::Receiver<ContactEvent>,
+    ) = async_channel::unbounded();
+    let (map_event_sender, map_event_receiver): (
+        async_broadcast::Sender<MapEvent>,
+        async_broadcast::Receiver<MapEvent>,
+    ) = async_broadcast::broadcast(3);
+    let map_event_receiver: InactiveReceiver<MapEvent> = map_event_receiver.deactivate();
 
-    let qrzcom_user = configuration.qrzcom_user;
-    let qrzcom_password = configuration.qrzcom_password;
-    let _task_enricher = tokio::spawn(async move {
-        enricher::run_enricher(
-            &qrzcom_user,
-            &qrzcom_password,
-            contact_info_receiver,
-            qso_sender,
+    if let Some(ingest_tcp) = configuration.ingest_tcp {
+        let contact_event_sender = contact_event_sender.clone();
+        let _task_tcp_ingest = tokio::spawn(async move {
+            if let Err(e) = ingest::run_tcp_ingest(&ingest_tcp, contact_event_sender).await {
+                log::error!("TCP ingest {} stopped: {}", ingest_tcp, e);
+            }
+        });
+    }
+
+    if let Some(ingest_unix) = configuration.ingest_unix {
+        #[cfg(unix)]
+        {
+            let contact_event_sender = contact_event_sender.clone();
+            let _task_unix_ingest = tokio::spawn(async move {
+                if let Err(e) = ingest::run_unix_ingest(&ingest_unix, contact_event_sender).await {
+                    log::error!("Unix ingest {} stopped: {}", ingest_unix.display(), e);
+                }
+            });
+        }
+        #[cfg(not(unix))]
+        log::error!(
+            "Unix ingest {} is not supported on this platform",
+            ingest_unix.display()
+        );
+    }
+
+    if let Some(watch_file) = configuration.watch_file {
+        let watch_format = configuration
+            .watch_format
+            .unwrap_or(LogFormat::from_path(&watch_file));
+        let contact_event_sender = contact_event_sender.clone();
+        let _task_watcher = tokio::spawn(async move {
+            if let Err(e) =
+                watcher::run_file_watcher(&watch_file, watch_format, contact_event_sender).await
+            {
+                log::error!("File watcher {} stopped: {}", watch_file.display(), e);
+            }
+        });
+    }
+
+    let sources = if configuration.sources.is_empty() {
+        vec![Source {
+            protocol: configuration.bind_protocol,
+            host: configuration.bind_host,
+            port: configuration.bind_port,
+            label: None,
+            multicast_group: configuration.bind_multicast_group,
+            multicast_interface: configuration.bind_multicast_interface,
+            reuse: configuration.bind_reuse,
+        }]
+    } else {
+        configuration.sources
+    };
+
+    let datagram_filter = Arc::new(DatagramFilter::new(
+        configuration.allow_list,
+        configuration.hmac_secret,
+    ));
+
+    for source in sources {
+        let datagram_filter = datagram_filter.clone();
+        let contact_event_sender = contact_event_sender.clone();
+        let max_datagram_size = configuration.max_datagram_size as usize;
+        let _task_receiver = tokio::spawn(async move {
+            run_source(
+                source,
+                &datagram_filter,
+                max_datagram_size,
+                contact_event_sender,
+            )
+            .await;
+        });
+    }
+
+    let deduplicator = Deduplicator::new(
+        configuration.dupe_policy,
+        Duration::from_secs(configuration.dedup_window),
+    );
+    let _task_deduplicator = tokio::spawn(async move {
+        dedup::run_deduplicator(
+            deduplicator,
+            contact_event_receiver,
+            unique_contact_event_sender,
         )
         .await
     });
 
+    let mut callbook_providers: Vec<Box<dyn CallbookProvider>> = Vec::new();
+    for callbook in configuration.callbooks {
+        match callbook {
+            CallbookKind::Qrzcom => {
+                match (&configuration.qrzcom_user, &configuration.qrzcom_password) {
+                    (Some(user), Some(password)) => callbook_providers.push(Box::new(
+                        QRZComClient::new(user, password)
+                            .map_err(|e| std::io::Error::other(e.to_string()))?,
+                    )),
+                    _ => log::info!("QRZ.com credentials not configured, skipping provider"),
+                }
+            }
+            CallbookKind::Hamqth => {
+                match (&configuration.hamqth_user, &configuration.hamqth_password) {
+                    (Some(user), Some(password)) => callbook_providers.push(Box::new(
+                        HamQTHClient::new(user, password)
+                            .map_err(|e| std::io::Error::other(e.to_string()))?,
+                    )),
+                    _ => log::info!("HamQTH credentials not configured, skipping provider"),
+                }
+            }
+        }
+    }
+    let throttle_settings = ThrottleSettings {
+        rate_limit: configuration.callbook_rate_limit,
+        burst: configuration.callbook_burst,
+        failure_threshold: configuration.callbook_failure_threshold,
+        probe_interval: Duration::from_secs(configuration.callbook_probe_interval),
+    };
+    let callbook_chain = CallbookChain::new(
+        callbook_providers
+            .into_iter()
+            .map(|provider| -> Box<dyn CallbookProvider> {
+                Box::new(ThrottledProvider::new(provider, throttle_settings))
+            })
+            .collect(),
+    );
+
+    let dxcc_resolver = match &configuration.cty_file {
+        Some(cty_file) => Some(DxccResolver::load(cty_file).map_err(|e| {
+            std::io::Error::other(format!(
+                "Unable to load country file {}: {}",
+                cty_file.display(),
+                e
+            ))
+        })?),
+        None => None,
+    };
+
+    match (callbook_chain.is_empty(), &dxcc_resolver) {
+        (true, None) => log::warn!(
+            "No callbook provider or country file configured, contacts without location will not be enriched"
+        ),
+        (true, Some(_)) => log::info!("No callbook provider configured, running in offline mode"),
+        _ => {}
+    }
+
+    let callsign_cache = Arc::new(CallsignCache::new(
+        configuration.cache_file,
+        Duration::from_secs(configuration.cache_max_age),
+    ));
+    let _task_cache_persister = tokio::spawn(cache::run_cache_persister(
+        callsign_cache.clone(),
+        Duration::from_secs(60),
+    ));
+
     let home_point = Point {
         latitude: configuration.home_latitude,
         longitude: configuration.home_longitude,
     };
 
+    let override_table = Arc::new(
+        OverrideTable::load(configuration.overrides_file)
+            .map_err(|e| std::io::Error::other(format!("Unable to load overrides file: {}", e)))?,
+    );
+    let _task_override_reloader = tokio::spawn(overrides::run_override_reloader(
+        override_table.clone(),
+        Duration::from_secs(10),
+    ));
+
+    let dead_letters = Arc::new(DeadLetters::default());
+
+    let enricher_context = EnricherContext {
+        home_point,
+        callbook_provider: Box::new(callbook_chain),
+        callsign_cache: callsign_cache.clone(),
+        dxcc_resolver,
+        overrides: override_table.clone(),
+        unknown_location_policy: configuration.unknown_location,
+        hold_interval: Duration::from_secs(configuration.hold_interval),
+        hold_attempts: configuration.hold_attempts,
+        workers: configuration.enricher_workers as usize,
+        preserve_order: configuration.preserve_order,
+        retry_attempts: configuration.lookup_retries,
+        retry_delay: Duration::from_secs(configuration.lookup_retry_delay),
+        dead_letters: dead_letters.clone(),
+    };
+    let _task_enricher = tokio::spawn(async move {
+        if let Err(e) = enricher::run_enricher(
+            enricher_context,
+            unique_contact_event_receiver,
+            map_event_sender,
+        )
+        .await
+        {
+            log::error!("Enricher stopped: {}", e);
+        }
+    });
+
     http::run_http_server(
         &configuration.http_host,
         configuration.http_port,
-        home_point,
-        qso_receiver,
+        HttpContext {
+            home_point,
+            api_token: ApiToken(configuration.api_token),
+            contact_event_sender,
+            map_event_receiver,
+            datagram_filter,
+            callsign_cache,
+            dead_letters,
+            override_table,
+        },
     )
     .await
 }
+
+async fn run_source(
+    source: Source,
+    datagram_filter: &DatagramFilter,
+    max_datagram_size: usize,
+    contact_event_sender: async_channel::Sender<ContactEvent>,
+) {
+    log::info!("Starting receiver {}", source);
+
+    let result = match source.protocol {
+        ReceiverProtocol::Xml | ReceiverProtocol::Adif => receiver::run_receiver(
+            &source,
+            datagram_filter,
+            max_datagram_size,
+            contact_event_sender,
+        )
+        .await
+        .map_err(|e| e.to_string()),
+        ReceiverProtocol::Wsjtx => wsjtx::run_wsjtx_receiver(
+            &source,
+            datagram_filter,
+            max_datagram_size,
+            contact_event_sender,
+        )
+        .await
+        .map_err(|e| e.to_string()),
+    };
+
+    if let Err(e) = result {
+        log::error!("Receiver {} stopped: {}", source, e);
+    }
+}
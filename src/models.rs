@@ -14,10 +14,111 @@
  *
  */
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
 pub struct Point {
     pub latitude: f64,
     pub longitude: f64,
 }
+
+const EARTH_RADIUS_KM: f64 = 6371.0088;
+const KM_PER_MILE: f64 = 1.609344;
+
+impl Point {
+    pub fn distance_km(&self, other: &Point) -> f64 {
+        let (latitude1, latitude2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let delta_latitude = latitude2 - latitude1;
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let a = (delta_latitude / 2.0).sin().powi(2)
+            + latitude1.cos() * latitude2.cos() * (delta_longitude / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS_KM * a.sqrt().min(1.0).asin()
+    }
+
+    pub fn bearing_to(&self, other: &Point) -> f64 {
+        let (latitude1, latitude2) = (self.latitude.to_radians(), other.latitude.to_radians());
+        let delta_longitude = (other.longitude - self.longitude).to_radians();
+
+        let y = delta_longitude.sin() * latitude2.cos();
+        let x = latitude1.cos() * latitude2.sin()
+            - latitude1.sin() * latitude2.cos() * delta_longitude.cos();
+        y.atan2(x).to_degrees().rem_euclid(360.0)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Clone, Copy)]
+pub struct PathMetrics {
+    pub distance_km: f64,
+    pub distance_mi: f64,
+    pub bearing: f64,
+    pub reverse_bearing: f64,
+    pub long_path: bool,
+}
+
+impl PathMetrics {
+    pub fn new(home: &Point, station: &Point, long_path: bool) -> Self {
+        let mut distance_km = home.distance_km(station);
+        let mut bearing = home.bearing_to(station);
+        let mut reverse_bearing = station.bearing_to(home);
+
+        if long_path {
+            distance_km = 2.0 * std::f64::consts::PI * EARTH_RADIUS_KM - distance_km;
+            bearing = (bearing + 180.0).rem_euclid(360.0);
+            reverse_bearing = (reverse_bearing + 180.0).rem_euclid(360.0);
+        }
+
+        Self {
+            distance_km: round(distance_km),
+            distance_mi: round(distance_km / KM_PER_MILE),
+            bearing: round(bearing) % 360.0,
+            reverse_bearing: round(reverse_bearing) % 360.0,
+            long_path,
+        }
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::models::{PathMetrics, Point};
+
+    const HOME: Point = Point {
+        latitude: 39.2238,
+        longitude: 9.1217,
+    };
+
+    const STATION: Point = Point {
+        latitude: 41.7147,
+        longitude: -72.7272,
+    };
+
+    #[test]
+    fn test_short_path() {
+        let path = PathMetrics::new(&HOME, &STATION, false);
+        assert_eq!(path.distance_km, 6651.3);
+        assert_eq!(path.distance_mi, 4132.9);
+        assert_eq!(path.bearing, 301.3);
+        assert_eq!(path.reverse_bearing, 62.5);
+        assert!(!path.long_path);
+    }
+
+    #[test]
+    fn test_long_path() {
+        let path = PathMetrics::new(&HOME, &STATION, true);
+        assert_eq!(path.distance_km, 33378.9);
+        assert_eq!(path.bearing, 121.3);
+        assert_eq!(path.reverse_bearing, 242.5);
+        assert!(path.long_path);
+    }
+
+    #[test]
+    fn test_same_point() {
+        let path = PathMetrics::new(&HOME, &HOME, false);
+        assert_eq!(path.distance_km, 0.0);
+        assert_eq!(path.bearing, 0.0);
+    }
+}
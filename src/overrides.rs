@@ -0,0 +1,560 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::maidenhead;
+use crate::models::Point;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+#[derive(Debug)]
+pub enum OverrideError {
+    IO(std::io::Error),
+    Parsing(toml::de::Error),
+    Serialization(toml::ser::Error),
+    MalformedRecord(String),
+    InvalidPattern(regex::Error),
+    InvalidEntry(String),
+}
+
+impl Display for OverrideError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OverrideError::IO(e) => {
+                write!(f, "IO error: {}", e)
+            }
+            OverrideError::Parsing(e) => {
+                write!(f, "Parsing error: {}", e)
+            }
+            OverrideError::Serialization(e) => {
+                write!(f, "Serialization error: {}", e)
+            }
+            OverrideError::MalformedRecord(record) => {
+                write!(f, "Malformed record: {}", record)
+            }
+            OverrideError::InvalidPattern(e) => {
+                write!(f, "Invalid pattern: {}", e)
+            }
+            OverrideError::InvalidEntry(reason) => {
+                write!(f, "Invalid entry: {}", reason)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for OverrideError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+impl From<toml::de::Error> for OverrideError {
+    fn from(value: toml::de::Error) -> Self {
+        Self::Parsing(value)
+    }
+}
+
+impl From<toml::ser::Error> for OverrideError {
+    fn from(value: toml::ser::Error) -> Self {
+        Self::Serialization(value)
+    }
+}
+
+impl From<regex::Error> for OverrideError {
+    fn from(value: regex::Error) -> Self {
+        Self::InvalidPattern(value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocationOverride {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub call: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub longitude: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub grid: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+}
+
+impl LocationOverride {
+    pub fn location(&self) -> Option<Point> {
+        match (self.latitude, self.longitude) {
+            (Some(latitude), Some(longitude)) => Some(Point {
+                latitude,
+                longitude,
+            }),
+            _ => None,
+        }
+    }
+
+    fn normalize(mut self) -> Result<Self, OverrideError> {
+        self.call = non_empty(self.call).map(|call| call.to_uppercase());
+        self.pattern = non_empty(self.pattern);
+        self.grid = non_empty(self.grid).map(|grid| grid.to_uppercase());
+        self.note = non_empty(self.note);
+
+        if self.call.is_some() == self.pattern.is_some() {
+            return Err(OverrideError::InvalidEntry(
+                "exactly one of call and pattern is required".to_string(),
+            ));
+        }
+
+        match (self.latitude, self.longitude, &self.grid) {
+            (Some(latitude), Some(longitude), _) => {
+                if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                    return Err(OverrideError::InvalidEntry(
+                        "coordinates out of range".to_string(),
+                    ));
+                }
+            }
+            (None, None, Some(grid)) => {
+                if let Err(e) = maidenhead::decode(grid) {
+                    return Err(OverrideError::InvalidEntry(format!(
+                        "invalid grid {}: {}",
+                        grid, e
+                    )));
+                }
+            }
+            (None, None, None) => {
+                return Err(OverrideError::InvalidEntry(
+                    "latitude and longitude or grid are required".to_string(),
+                ))
+            }
+            _ => {
+                return Err(OverrideError::InvalidEntry(
+                    "latitude and longitude must be provided together".to_string(),
+                ))
+            }
+        }
+
+        Ok(self)
+    }
+
+    fn same_key(&self, other: &LocationOverride) -> bool {
+        self.call == other.call && self.pattern == other.pattern
+    }
+}
+
+impl Display for LocationOverride {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match (&self.call, &self.pattern) {
+            (Some(call), _) => write!(f, "{}", call),
+            (None, Some(pattern)) => write!(f, "/{}/", pattern),
+            (None, None) => write!(f, "-"),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct OverrideFile {
+    #[serde(default, rename = "override")]
+    overrides: Vec<LocationOverride>,
+}
+
+#[derive(Debug, Default)]
+struct Overrides {
+    entries: Vec<LocationOverride>,
+    calls: HashMap<String, usize>,
+    patterns: Vec<(Regex, usize)>,
+}
+
+impl Overrides {
+    fn new(entries: Vec<LocationOverride>) -> Result<Self, OverrideError> {
+        let mut overrides = Self::default();
+
+        for entry in entries {
+            let entry = entry.normalize()?;
+            let index = match overrides
+                .entries
+                .iter()
+                .position(|existing| existing.same_key(&entry))
+            {
+                Some(index) => {
+                    overrides.entries[index] = entry;
+                    continue;
+                }
+                None => overrides.entries.len(),
+            };
+
+            match (&entry.call, &entry.pattern) {
+                (Some(call), _) => {
+                    overrides.calls.insert(call.clone(), index);
+                }
+                (None, Some(pattern)) => {
+                    let regex = Regex::new(&format!("^(?i:{})$", pattern))?;
+                    overrides.patterns.push((regex, index));
+                }
+                (None, None) => {}
+            }
+            overrides.entries.push(entry);
+        }
+
+        Ok(overrides)
+    }
+
+    fn resolve(&self, call: &str) -> Option<&LocationOverride> {
+        let call = call.trim().to_uppercase();
+        let index = self.calls.get(&call).copied().or_else(|| {
+            self.patterns
+                .iter()
+                .find(|(regex, _)| regex.is_match(&call))
+                .map(|(_, index)| *index)
+        })?;
+        self.entries.get(index)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OverrideTable {
+    path: Option<PathBuf>,
+    overrides: RwLock<Overrides>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+impl OverrideTable {
+    pub fn load(path: Option<PathBuf>) -> Result<Self, OverrideError> {
+        let table = Self {
+            path,
+            ..Default::default()
+        };
+
+        if table.path.as_ref().is_some_and(|path| path.exists()) {
+            table.reload()?;
+        }
+
+        Ok(table)
+    }
+
+    pub fn reload(&self) -> Result<usize, OverrideError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(self.len()),
+        };
+
+        let modified = modified(path);
+        let overrides = Overrides::new(read(path)?)?;
+        let count = overrides.entries.len();
+
+        *self.overrides.write().unwrap() = overrides;
+        *self.modified.lock().unwrap() = modified;
+
+        log::info!("Loaded {} overrides from {}", count, path.display());
+        Ok(count)
+    }
+
+    pub fn resolve(&self, call: &str) -> Option<LocationOverride> {
+        self.overrides.read().unwrap().resolve(call).cloned()
+    }
+
+    pub fn list(&self) -> Vec<LocationOverride> {
+        self.overrides.read().unwrap().entries.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.overrides.read().unwrap().entries.len()
+    }
+
+    pub fn upsert(&self, entry: LocationOverride) -> Result<LocationOverride, OverrideError> {
+        let entry = entry.normalize()?;
+        let mut overrides = self.overrides.write().unwrap();
+
+        let mut entries = overrides.entries.clone();
+        match entries
+            .iter()
+            .position(|existing| existing.same_key(&entry))
+        {
+            Some(index) => entries[index] = entry.clone(),
+            None => entries.push(entry.clone()),
+        }
+
+        let updated = Overrides::new(entries)?;
+        self.save(&updated.entries)?;
+        *overrides = updated;
+
+        Ok(entry)
+    }
+
+    pub fn remove(&self, call: Option<&str>, pattern: Option<&str>) -> Result<bool, OverrideError> {
+        let call = call.map(|call| call.trim().to_uppercase());
+        let mut overrides = self.overrides.write().unwrap();
+
+        let entries: Vec<LocationOverride> = overrides
+            .entries
+            .iter()
+            .filter(|entry| entry.call != call || entry.pattern.as_deref() != pattern)
+            .cloned()
+            .collect();
+        if entries.len() == overrides.entries.len() {
+            return Ok(false);
+        }
+
+        let updated = Overrides::new(entries)?;
+        self.save(&updated.entries)?;
+        *overrides = updated;
+
+        Ok(true)
+    }
+
+    fn save(&self, entries: &[LocationOverride]) -> Result<(), OverrideError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        let payload = match is_csv(path) {
+            true => to_csv(entries)?,
+            false => toml::to_string(&OverrideFile {
+                overrides: entries.to_vec(),
+            })?,
+        };
+
+        let mut temp_path = path.clone().into_os_string();
+        temp_path.push(".tmp");
+        std::fs::write(&temp_path, payload).and_then(|_| std::fs::rename(&temp_path, path))?;
+        *self.modified.lock().unwrap() = modified(path);
+
+        log::debug!("Overrides saved to {}", path.display());
+        Ok(())
+    }
+
+    fn is_stale(&self) -> bool {
+        match &self.path {
+            Some(path) => modified(path)
+                .is_some_and(|modified| Some(modified) != *self.modified.lock().unwrap()),
+            None => false,
+        }
+    }
+}
+
+fn read(path: &Path) -> Result<Vec<LocationOverride>, OverrideError> {
+    let payload = std::fs::read_to_string(path)?;
+    match is_csv(path) {
+        true => from_csv(&payload),
+        false => Ok(toml::from_str::<OverrideFile>(&payload)?.overrides),
+    }
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"))
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn from_csv(payload: &str) -> Result<Vec<LocationOverride>, OverrideError> {
+    let mut entries = Vec::new();
+
+    for line in payload.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("call,") {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.splitn(6, ',').map(str::trim).collect();
+        if fields.len() < 5 {
+            return Err(OverrideError::MalformedRecord(line.to_string()));
+        }
+
+        let coordinate = |value: &str| match value {
+            "" => Ok(None),
+            value => value
+                .parse()
+                .map(Some)
+                .map_err(|_| OverrideError::MalformedRecord(line.to_string())),
+        };
+
+        entries.push(LocationOverride {
+            call: Some(fields[0].to_string()),
+            pattern: Some(fields[1].to_string()),
+            latitude: coordinate(fields[2])?,
+            longitude: coordinate(fields[3])?,
+            grid: Some(fields[4].to_string()),
+            note: fields.get(5).map(|note| note.to_string()),
+        });
+    }
+
+    Ok(entries)
+}
+
+fn to_csv(entries: &[LocationOverride]) -> Result<String, OverrideError> {
+    let mut payload = String::from("call,pattern,latitude,longitude,grid,note\n");
+
+    for entry in entries {
+        if [&entry.call, &entry.pattern, &entry.grid]
+            .into_iter()
+            .flatten()
+            .any(|value| value.contains(','))
+        {
+            return Err(OverrideError::InvalidEntry(format!(
+                "{} cannot be stored in a CSV file, use TOML instead",
+                entry
+            )));
+        }
+
+        let field = |value: &Option<String>| value.clone().unwrap_or_default();
+        let coordinate = |value: Option<f64>| value.map(|v| v.to_string()).unwrap_or_default();
+        payload.push_str(&format!(
+            "{},{},{},{},{},{}\n",
+            field(&entry.call),
+            field(&entry.pattern),
+            coordinate(entry.latitude),
+            coordinate(entry.longitude),
+            field(&entry.grid),
+            field(&entry.note).replace(['\r', '\n'], " ")
+        ));
+    }
+
+    Ok(payload)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+pub async fn run_override_reloader(override_table: Arc<OverrideTable>, interval: Duration) {
+    let mut interval = tokio::time::interval(interval);
+    loop {
+        interval.tick().await;
+        if override_table.is_stale() {
+            if let Err(e) = override_table.reload() {
+                log::warn!("Unable to reload overrides: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::overrides::{from_csv, LocationOverride, OverrideTable, Overrides};
+
+    fn entry(call: Option<&str>, pattern: Option<&str>, grid: &str) -> LocationOverride {
+        LocationOverride {
+            call: call.map(str::to_string),
+            pattern: pattern.map(str::to_string),
+            latitude: None,
+            longitude: None,
+            grid: Some(grid.to_string()),
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        let overrides = Overrides::new(vec![
+            entry(None, Some("K1[A-Z]{3}"), "FN31"),
+            entry(Some("k1abc"), None, "FN42"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            overrides.resolve("K1ABC").unwrap().grid.as_deref(),
+            Some("FN42")
+        );
+        assert_eq!(
+            overrides.resolve("k1xyz").unwrap().grid.as_deref(),
+            Some("FN31")
+        );
+        assert!(overrides.resolve("K1XYZ/P").is_none());
+        assert!(overrides.resolve("W1AW").is_none());
+    }
+
+    #[test]
+    fn test_invalid_entries() {
+        assert!(Overrides::new(vec![entry(Some("K1ABC"), Some("K1.*"), "FN31")]).is_err());
+        assert!(Overrides::new(vec![entry(Some("K1ABC"), None, "ZZ99")]).is_err());
+        assert!(Overrides::new(vec![entry(None, Some("K1("), "FN31")]).is_err());
+
+        let mut invalid = entry(Some("K1ABC"), None, "");
+        invalid.latitude = Some(41.7);
+        assert!(Overrides::new(vec![invalid]).is_err());
+    }
+
+    #[test]
+    fn test_from_csv() {
+        let entries = from_csv(
+            "call,pattern,latitude,longitude,grid,note
+# club station
+IS0AAA,,39.2,9.1,,Club call, registered far away
+,^IS0Z.*,,,JM49,
+",
+        )
+        .unwrap();
+
+        let overrides = Overrides::new(entries).unwrap();
+        let club = overrides.resolve("is0aaa").unwrap();
+        assert_eq!(club.latitude, Some(39.2));
+        assert_eq!(club.note.as_deref(), Some("Club call, registered far away"));
+        assert_eq!(
+            overrides.resolve("IS0ZZZ").unwrap().grid.as_deref(),
+            Some("JM49")
+        );
+
+        assert!(from_csv("IS0AAA,,abc,9.1,,").is_err());
+    }
+
+    #[test]
+    fn test_upsert_remove_reload() {
+        for extension in ["toml", "csv"] {
+            let path = std::env::temp_dir().join(format!(
+                "live-qso-map-overrides-{}.{}",
+                std::process::id(),
+                extension
+            ));
+            let _ = std::fs::remove_file(&path);
+
+            let table = OverrideTable::load(Some(path.clone())).unwrap();
+            assert_eq!(table.len(), 0);
+
+            table.upsert(entry(Some("k1abc"), None, "fn42")).unwrap();
+            table.upsert(entry(None, Some("K1.*"), "FN31")).unwrap();
+            table.upsert(entry(Some("K1ABC"), None, "FN43")).unwrap();
+            assert_eq!(table.len(), 2);
+
+            let reloaded = OverrideTable::load(Some(path.clone())).unwrap();
+            assert_eq!(reloaded.list(), table.list());
+            assert_eq!(
+                reloaded.resolve("K1ABC").unwrap().grid.as_deref(),
+                Some("FN43")
+            );
+
+            assert!(table.remove(Some("k1abc"), None).unwrap());
+            assert!(!table.remove(Some("K1ABC"), None).unwrap());
+            assert_eq!(reloaded.reload().unwrap(), 1);
+            assert_eq!(
+                reloaded.resolve("K1ABC").unwrap().grid.as_deref(),
+                Some("FN31")
+            );
+
+            std::fs::remove_file(&path).unwrap();
+        }
+    }
+}
@@ -14,10 +14,15 @@
  *
  */
 
+use crate::callbook::{
+    is_transient_request_error, CallbookError, CallbookProvider, CallbookRecord,
+};
+use async_trait::async_trait;
 use reqwest::{Client, Method};
 use serde::Deserialize;
 use std::fmt::Display;
 use std::time::Duration;
+use tokio::sync::Mutex;
 
 #[derive(Debug)]
 pub enum QRZComError {
@@ -42,6 +47,20 @@ impl Display for QRZComError {
     }
 }
 
+impl QRZComError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            QRZComError::Request(e) => is_transient_request_error(e),
+            QRZComError::Parsing(_) => true,
+            QRZComError::ApiError(_) => false,
+        }
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self, QRZComError::ApiError(message) if message.starts_with(NOT_FOUND))
+    }
+}
+
 impl From<reqwest::Error> for QRZComError {
     fn from(value: reqwest::Error) -> Self {
         Self::Request(value)
@@ -59,11 +78,13 @@ pub struct Callsign {
     pub call: Option<String>,
     pub lat: Option<f64>,
     pub lon: Option<f64>,
+    pub grid: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 struct Session {
+    key: Option<String>,
     error: Option<String>,
 }
 
@@ -74,36 +95,123 @@ struct ResponseBody {
     session: Session,
 }
 
-pub async fn call_xml_api(
-    username: &str,
-    password: &str,
-    callsign: &str,
-) -> Result<Callsign, QRZComError> {
-    let response_body: String = Client::new()
-        .request(Method::POST, "https://xmldata.qrz.com/xml/1.34/")
-        .body(format!(
-            "username={}&password={}&callsign={}",
-            username, password, &callsign
+const API_URL: &str = "https://xmldata.qrz.com/xml/1.34/";
+const AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+const NOT_FOUND: &str = "Not found";
+
+pub struct QRZComClient {
+    client: Client,
+    username: String,
+    password: String,
+    session_key: Mutex<Option<String>>,
+}
+
+impl QRZComClient {
+    pub fn new(username: &str, password: &str) -> Result<Self, QRZComError> {
+        let client = Client::builder().timeout(Duration::from_secs(5)).build()?;
+
+        Ok(Self {
+            client,
+            username: username.to_string(),
+            password: password.to_string(),
+            session_key: Mutex::new(None),
+        })
+    }
+
+    pub async fn lookup_callsign(&self, callsign: &str) -> Result<Callsign, QRZComError> {
+        let key = self.session_key(None).await?;
+
+        let mut response = self.query(&key, callsign).await?;
+        if response.session.key.is_none() {
+            log::info!(
+                "QRZ.com session is no longer valid ({}), logging in again",
+                response.session.error.unwrap_or_default()
+            );
+            let key = self.session_key(Some(&key)).await?;
+            response = self.query(&key, callsign).await?;
+        }
+
+        response.callsign.ok_or(QRZComError::ApiError(
+            response.session.error.unwrap_or_default(),
         ))
-        .timeout(Duration::from_secs(5))
-        .send()
-        .await?
-        .text()
-        .await?;
-
-    let response: ResponseBody = parse_response(&response_body)?;
-    response.callsign.ok_or(QRZComError::ApiError(
-        response.session.error.unwrap_or("".to_string()),
-    ))
+    }
+
+    async fn session_key(&self, expired_key: Option<&str>) -> Result<String, QRZComError> {
+        let mut session_key = self.session_key.lock().await;
+
+        match session_key.as_deref() {
+            Some(key) if Some(key) != expired_key => Ok(key.to_string()),
+            _ => {
+                *session_key = None;
+                let key = self.login().await?;
+                *session_key = Some(key.clone());
+                Ok(key)
+            }
+        }
+    }
+
+    async fn login(&self) -> Result<String, QRZComError> {
+        log::debug!("Logging in to QRZ.com as {}", self.username);
+
+        let response_body = self
+            .client
+            .request(Method::POST, API_URL)
+            .form(&[
+                ("username", self.username.as_str()),
+                ("password", self.password.as_str()),
+                ("agent", AGENT),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        let response = parse_response(&response_body)?;
+        response.session.key.ok_or(QRZComError::ApiError(
+            response.session.error.unwrap_or_default(),
+        ))
+    }
+
+    async fn query(&self, key: &str, callsign: &str) -> Result<ResponseBody, QRZComError> {
+        let response_body = self
+            .client
+            .request(Method::POST, API_URL)
+            .form(&[("s", key), ("callsign", callsign), ("agent", AGENT)])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+
+        Ok(parse_response(&response_body)?)
+    }
+}
+
+#[async_trait]
+impl CallbookProvider for QRZComClient {
+    fn name(&self) -> &str {
+        "QRZ.com"
+    }
+
+    async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+        let result = self.lookup_callsign(callsign).await?;
+        Ok(CallbookRecord {
+            call: result.call.unwrap_or(callsign.to_string()),
+            latitude: result.lat,
+            longitude: result.lon,
+            grid: result.grid.map(|grid| grid.to_uppercase()),
+        })
+    }
 }
 
 fn parse_response(payload: &str) -> Result<ResponseBody, serde_xml_rs::Error> {
-    serde_xml_rs::from_str(&payload)
+    serde_xml_rs::from_str(payload)
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::qrzcom::{parse_response, Callsign, ResponseBody, Session};
+    use crate::qrzcom::{parse_response, Callsign, QRZComError, ResponseBody, Session};
 
     #[test]
     fn test_parse_response_ok() {
@@ -120,11 +228,15 @@ mod tests {
 </QRZDatabase>";
 
         let expected = ResponseBody {
-            session: Session { error: None },
+            session: Session {
+                key: None,
+                error: None,
+            },
             callsign: Some(Callsign {
                 call: Some("IS0GVH".to_string()),
                 lat: Some(39.123456),
                 lon: Some(9.654321),
+                grid: Some("JM49".to_string()),
             }),
         };
 
@@ -133,6 +245,27 @@ mod tests {
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_parse_response_session_timeout() {
+        let input = "<QRZDatabase version=\"1.34\" xmlns=\"http://xmldata.qrz.com\">
+<Session>
+<Error>Session Timeout</Error>
+</Session>
+</QRZDatabase>";
+
+        let expected = ResponseBody {
+            session: Session {
+                key: None,
+                error: Some("Session Timeout".to_string()),
+            },
+            callsign: None,
+        };
+
+        let actual = parse_response(input).unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
     #[test]
     fn test_parse_response_error() {
         let input = "<QRZDatabase version=\"1.34\" xmlns=\"http://xmldata.qrz.com\">
@@ -143,6 +276,7 @@ mod tests {
 
         let expected = ResponseBody {
             session: Session {
+                key: None,
                 error: Some("Not found: ISGVH".to_string()),
             },
             callsign: None,
@@ -152,4 +286,23 @@ mod tests {
 
         assert_eq!(actual, expected);
     }
+
+    #[test]
+    fn test_parse_response_not_found_with_key() {
+        let input = "<QRZDatabase version=\"1.34\" xmlns=\"http://xmldata.qrz.com\">
+<Session>
+<Key>2331uf894c4bd29f3923f3bacf02c532d7bd9</Key>
+<Error>Not found: ISGVH</Error>
+</Session>
+</QRZDatabase>";
+
+        let actual = parse_response(input).unwrap();
+
+        assert_eq!(
+            actual.session.key,
+            Some("2331uf894c4bd29f3923f3bacf02c532d7bd9".to_string())
+        );
+        assert!(QRZComError::ApiError(actual.session.error.unwrap()).is_not_found());
+        assert!(!QRZComError::ApiError("Invalid session key".to_string()).is_not_found());
+    }
 }
@@ -14,28 +14,354 @@
  *
  */
 
+use crate::adif;
+use crate::filter::DatagramFilter;
+use crate::models::Point;
 use async_channel::Sender;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::ValueEnum;
+use encoding_rs::{Encoding, UTF_8, WINDOWS_1252};
 use serde::{Deserialize, Serialize};
+use socket2::{Domain, Protocol, Socket, Type};
 use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
 use tokio::net::UdpSocket;
 
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ContactInfo {
+    pub id: Option<String>,
+    pub source: Option<String>,
+    pub logger: Option<String>,
+    pub contest_name: Option<String>,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub my_call: Option<String>,
+    pub operator: Option<String>,
+    pub station_name: Option<String>,
     pub call: String,
     pub band: String,
+    pub mode: Option<String>,
+    pub tx_frequency: Option<u64>,
+    pub rx_frequency: Option<u64>,
+    pub country_prefix: Option<String>,
+    pub wpx_prefix: Option<String>,
+    pub grid: Option<String>,
+    pub location: Option<Point>,
+    pub sent_rst: Option<String>,
+    pub received_rst: Option<String>,
+    pub serial_number: Option<u32>,
+    pub exchange1: Option<String>,
+    pub exchange2: Option<String>,
+    pub exchange3: Option<String>,
+    #[serde(default)]
+    pub duplicate: bool,
+    #[serde(default)]
+    pub long_path: bool,
+    pub points: Option<u32>,
+}
+
+impl ContactInfo {
+    pub fn synthetic_id(&self) -> Option<String> {
+        self.timestamp
+            .map(|t| format!("{}-{}", self.call, t.format("%Y%m%d%H%M%S")))
+    }
 }
 
 impl Display for ContactInfo {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({})", self.call, self.band)
+        write!(f, "{} ({})", self.call, self.band)?;
+        if let Some(mode) = &self.mode {
+            write!(f, " {}", mode)?;
+        }
+        if self.duplicate {
+            write!(f, " [dupe]")?;
+        }
+        Ok(())
+    }
+}
+
+pub const MAX_DATAGRAM_SIZE: usize = 65507;
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ReceiverProtocol {
+    Xml,
+    Adif,
+    Wsjtx,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Source {
+    pub protocol: ReceiverProtocol,
+    pub host: String,
+    pub port: u16,
+    pub label: Option<String>,
+    pub multicast_group: Option<IpAddr>,
+    pub multicast_interface: Option<String>,
+    pub reuse: bool,
+}
+
+impl FromStr for Source {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (protocol, rest) = s
+            .split_once("://")
+            .ok_or(format!("missing protocol in {}", s))?;
+        let protocol = ReceiverProtocol::from_str(protocol, true)?;
+
+        let (rest, label) = match rest.split_once('#') {
+            Some((rest, label)) => (rest, Some(label.to_string()).filter(|l| !l.is_empty())),
+            None => (rest, None),
+        };
+
+        let (address, options) = match rest.split_once('?') {
+            Some((address, options)) => (address, options),
+            None => (rest, ""),
+        };
+
+        let mut multicast_group: Option<IpAddr> = None;
+        let mut multicast_interface: Option<String> = None;
+        let mut reuse = false;
+        for option in options.split('&').filter(|o| !o.is_empty()) {
+            let (key, value) = option.split_once('=').unwrap_or((option, "true"));
+            match key {
+                "group" => {
+                    let group: IpAddr = value
+                        .parse()
+                        .map_err(|_| format!("invalid multicast group in {}", s))?;
+                    if !group.is_multicast() {
+                        return Err(format!("{} is not a multicast group", group));
+                    }
+                    multicast_group = Some(group);
+                }
+                "interface" => multicast_interface = Some(value.to_string()),
+                "reuse" => {
+                    reuse = value
+                        .parse()
+                        .map_err(|_| format!("invalid reuse flag in {}", s))?
+                }
+                _ => return Err(format!("unknown option {} in {}", key, s)),
+            }
+        }
+
+        let (host, port) = address
+            .rsplit_once(':')
+            .ok_or(format!("missing port in {}", s))?;
+        let host = host.trim_start_matches('[').trim_end_matches(']');
+        if host.is_empty() {
+            return Err(format!("missing host in {}", s));
+        }
+        let port: u16 = port.parse().map_err(|_| format!("invalid port in {}", s))?;
+
+        Ok(Source {
+            protocol,
+            host: host.to_string(),
+            port,
+            label,
+            multicast_group,
+            multicast_interface,
+            reuse,
+        })
+    }
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let protocol = self
+            .protocol
+            .to_possible_value()
+            .map(|v| v.get_name().to_string())
+            .unwrap_or_default();
+
+        if self.host.contains(':') {
+            write!(f, "{}://[{}]:{}", protocol, self.host, self.port)?;
+        } else {
+            write!(f, "{}://{}:{}", protocol, self.host, self.port)?;
+        }
+
+        let mut options: Vec<String> = Vec::new();
+        if let Some(group) = &self.multicast_group {
+            options.push(format!("group={}", group));
+        }
+        if let Some(interface) = &self.multicast_interface {
+            options.push(format!("interface={}", interface));
+        }
+        if self.reuse {
+            options.push("reuse=true".to_string());
+        }
+        if !options.is_empty() {
+            write!(f, "?{}", options.join("&"))?;
+        }
+
+        match &self.label {
+            Some(label) => write!(f, "#{}", label),
+            None => Ok(()),
+        }
+    }
+}
+
+pub async fn bind_socket(source: &Source) -> std::io::Result<UdpSocket> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((source.host.as_str(), source.port))
+        .await?
+        .collect();
+    let address = bind_address(&source.host, &addresses, source.multicast_group)?;
+
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    if source.reuse {
+        socket.set_reuse_address(true)?;
+        #[cfg(all(unix, not(any(target_os = "solaris", target_os = "illumos"))))]
+        socket.set_reuse_port(true)?;
+    }
+    socket.bind(&address.into())?;
+
+    match source.multicast_group {
+        Some(IpAddr::V4(group)) => {
+            let interface = match &source.multicast_interface {
+                Some(interface) => interface.parse::<Ipv4Addr>().map_err(|_| {
+                    std::io::Error::new(
+                        ErrorKind::InvalidInput,
+                        format!("invalid IPv4 multicast interface address {}", interface),
+                    )
+                })?,
+                None => Ipv4Addr::UNSPECIFIED,
+            };
+            socket.join_multicast_v4(&group, &interface)?;
+            log::info!("Joined multicast group {} on {}", group, interface);
+        }
+        Some(IpAddr::V6(group)) => {
+            let interface = match &source.multicast_interface {
+                Some(interface) => interface_index(interface)?,
+                None => 0,
+            };
+            socket.join_multicast_v6(&group, interface)?;
+            log::info!(
+                "Joined multicast group {} on interface {}",
+                group,
+                interface
+            );
+        }
+        None => {}
+    }
+
+    socket.set_nonblocking(true)?;
+    UdpSocket::from_std(socket.into())
+}
+
+fn bind_address(
+    host: &str,
+    addresses: &[SocketAddr],
+    multicast_group: Option<IpAddr>,
+) -> std::io::Result<SocketAddr> {
+    let first = addresses.first().ok_or(std::io::Error::new(
+        ErrorKind::AddrNotAvailable,
+        format!("unable to resolve {}", host),
+    ))?;
+
+    let Some(group) = multicast_group else {
+        return Ok(*first);
+    };
+
+    if let Some(address) = addresses
+        .iter()
+        .find(|address| address.is_ipv4() == group.is_ipv4())
+    {
+        return Ok(*address);
+    }
+
+    if first.ip().is_unspecified() {
+        let unspecified = match group {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+        };
+        return Ok(SocketAddr::new(unspecified, first.port()));
+    }
+
+    Err(std::io::Error::new(
+        ErrorKind::InvalidInput,
+        format!(
+            "{} has no address of the same family as multicast group {}",
+            host, group
+        ),
+    ))
+}
+
+fn interface_index(interface: &str) -> std::io::Result<u32> {
+    if let Ok(index) = interface.parse::<u32>() {
+        return Ok(index);
+    }
+
+    #[cfg(unix)]
+    if let Ok(name) = std::ffi::CString::new(interface) {
+        let index = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if index != 0 {
+            return Ok(index);
+        }
+    }
+
+    Err(std::io::Error::new(
+        ErrorKind::InvalidInput,
+        format!("invalid IPv6 multicast interface {}", interface),
+    ))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ContactEvent {
+    Insert(ContactInfo),
+    Replace(ContactInfo),
+    Delete(ContactDeletion),
+}
+
+impl ContactEvent {
+    pub fn set_source(&mut self, source: &Option<String>) {
+        match self {
+            ContactEvent::Insert(contact_info) | ContactEvent::Replace(contact_info) => {
+                contact_info.source.clone_from(source);
+            }
+            ContactEvent::Delete(_) => {}
+        }
+    }
+}
+
+impl Display for ContactEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContactEvent::Insert(contact_info) => write!(f, "insert {}", contact_info),
+            ContactEvent::Replace(contact_info) => write!(f, "replace {}", contact_info),
+            ContactEvent::Delete(contact_deletion) => write!(f, "delete {}", contact_deletion),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ContactDeletion {
+    pub id: String,
+    pub call: Option<String>,
+}
+
+impl Display for ContactDeletion {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.call {
+            Some(call) => write!(f, "{} [{}]", call, self.id),
+            None => write!(f, "[{}]", self.id),
+        }
     }
 }
 
 #[derive(Debug)]
 pub enum ReceiverError {
     UDPSocket(std::io::Error),
+    TruncatedDatagram(SocketAddr, usize),
+    Encoding(String),
     XMLParsing(serde_xml_rs::Error),
-    QueueSenderError(async_channel::SendError<ContactInfo>),
+    FieldParsing(String, String),
+    UnsupportedMessage(String),
+    ADIFParsing(adif::ADIFError),
+    QueueSenderError(Box<async_channel::SendError<ContactEvent>>),
 }
 
 impl From<std::io::Error> for ReceiverError {
@@ -50,9 +376,28 @@ impl Display for ReceiverError {
             ReceiverError::UDPSocket(e) => {
                 write!(f, "UDP Socket error: {}", e)
             }
+            ReceiverError::TruncatedDatagram(addr, max_size) => {
+                write!(
+                    f,
+                    "Truncated datagram from {}: more than {} bytes",
+                    addr, max_size
+                )
+            }
+            ReceiverError::Encoding(e) => {
+                write!(f, "Invalid {} payload", e)
+            }
             ReceiverError::XMLParsing(e) => {
                 write!(f, "XML Parsing error: {}", e)
             }
+            ReceiverError::FieldParsing(field, value) => {
+                write!(f, "Invalid value for field {}: {}", field, value)
+            }
+            ReceiverError::UnsupportedMessage(e) => {
+                write!(f, "Unsupported message: {}", e)
+            }
+            ReceiverError::ADIFParsing(e) => {
+                write!(f, "ADIF Parsing error: {}", e)
+            }
             ReceiverError::QueueSenderError(e) => {
                 write!(f, "Queue sender error: {}", e)
             }
@@ -66,46 +411,802 @@ impl From<serde_xml_rs::Error> for ReceiverError {
     }
 }
 
-impl From<async_channel::SendError<ContactInfo>> for ReceiverError {
-    fn from(value: async_channel::SendError<ContactInfo>) -> Self {
-        Self::QueueSenderError(value)
+impl From<adif::ADIFError> for ReceiverError {
+    fn from(value: adif::ADIFError) -> Self {
+        Self::ADIFParsing(value)
+    }
+}
+
+impl From<async_channel::SendError<ContactEvent>> for ReceiverError {
+    fn from(value: async_channel::SendError<ContactEvent>) -> Self {
+        Self::QueueSenderError(Box::new(value))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RawContactInfo {
+    #[serde(alias = "ID")]
+    id: Option<String>,
+    app: Option<String>,
+    logger: Option<String>,
+    contestname: Option<String>,
+    timestamp: Option<String>,
+    mycall: Option<String>,
+    operator: Option<String>,
+    #[serde(alias = "StationName")]
+    stationname: Option<String>,
+    call: String,
+    band: String,
+    mode: Option<String>,
+    txfreq: Option<String>,
+    rxfreq: Option<String>,
+    countryprefix: Option<String>,
+    wpxprefix: Option<String>,
+    snt: Option<String>,
+    rcv: Option<String>,
+    nr: Option<String>,
+    rcvnr: Option<String>,
+    gridsquare: Option<String>,
+    exch1: Option<String>,
+    exch2: Option<String>,
+    exch3: Option<String>,
+    exchange1: Option<String>,
+    section: Option<String>,
+    duplicate: Option<String>,
+    points: Option<String>,
+}
+
+impl RawContactInfo {
+    fn is_n1mm(&self) -> bool {
+        self.app.is_some() || self.rxfreq.is_some()
+    }
+}
+
+impl TryFrom<RawContactInfo> for ContactInfo {
+    type Error = ReceiverError;
+
+    fn try_from(value: RawContactInfo) -> Result<Self, Self::Error> {
+        if value.is_n1mm() {
+            n1mm_contact_info(value)
+        } else {
+            qartest_contact_info(value)
+        }
     }
 }
 
+fn qartest_contact_info(value: RawContactInfo) -> Result<ContactInfo, ReceiverError> {
+    Ok(ContactInfo {
+        id: non_empty(value.id),
+        source: None,
+        logger: non_empty(value.logger),
+        contest_name: non_empty(value.contestname),
+        timestamp: parse_timestamp(non_empty(value.timestamp))?,
+        my_call: non_empty(value.mycall),
+        operator: non_empty(value.operator),
+        station_name: non_empty(value.stationname),
+        call: value.call.trim().to_uppercase(),
+        band: value.band.trim().to_string(),
+        mode: non_empty(value.mode),
+        tx_frequency: parse_frequency("txfreq", non_empty(value.txfreq))?,
+        rx_frequency: None,
+        country_prefix: non_empty(value.countryprefix),
+        wpx_prefix: non_empty(value.wpxprefix),
+        grid: None,
+        location: None,
+        sent_rst: non_empty(value.snt),
+        received_rst: non_empty(value.rcv),
+        serial_number: parse_number("nr", non_empty(value.nr))?,
+        exchange1: non_empty(value.exch1),
+        exchange2: non_empty(value.exch2),
+        exchange3: non_empty(value.exch3),
+        duplicate: parse_bool(non_empty(value.duplicate)),
+        long_path: false,
+        points: parse_number("points", non_empty(value.points))?,
+    })
+}
+
+fn n1mm_contact_info(value: RawContactInfo) -> Result<ContactInfo, ReceiverError> {
+    Ok(ContactInfo {
+        id: non_empty(value.id),
+        source: None,
+        logger: non_empty(value.app),
+        contest_name: non_empty(value.contestname),
+        timestamp: parse_timestamp(non_empty(value.timestamp))?,
+        my_call: non_empty(value.mycall),
+        operator: non_empty(value.operator),
+        station_name: non_empty(value.stationname),
+        call: value.call.trim().to_uppercase(),
+        band: band_from_mhz(&value.band)?,
+        mode: non_empty(value.mode),
+        tx_frequency: parse_frequency("txfreq", non_empty(value.txfreq))?,
+        rx_frequency: parse_frequency("rxfreq", non_empty(value.rxfreq))?,
+        country_prefix: non_empty(value.countryprefix),
+        wpx_prefix: non_empty(value.wpxprefix),
+        grid: non_empty(value.gridsquare).map(|g| g.to_uppercase()),
+        location: None,
+        sent_rst: non_empty(value.snt),
+        received_rst: non_empty(value.rcv),
+        serial_number: parse_number::<u32>("rcvnr", non_empty(value.rcvnr))?.filter(|&n| n > 0),
+        exchange1: non_empty(value.exchange1),
+        exchange2: non_empty(value.section),
+        exchange3: None,
+        duplicate: false,
+        long_path: false,
+        points: parse_number("points", non_empty(value.points))?,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+struct RawContactDeletion {
+    #[serde(alias = "ID")]
+    id: Option<String>,
+    call: Option<String>,
+}
+
+impl TryFrom<RawContactDeletion> for ContactDeletion {
+    type Error = ReceiverError;
+
+    fn try_from(value: RawContactDeletion) -> Result<Self, Self::Error> {
+        Ok(ContactDeletion {
+            id: non_empty(value.id).ok_or(ReceiverError::FieldParsing(
+                "id".to_string(),
+                "".to_string(),
+            ))?,
+            call: non_empty(value.call).map(|c| c.to_uppercase()),
+        })
+    }
+}
+
+pub async fn recv_datagram(
+    sock: &UdpSocket,
+    buf: &mut [u8],
+) -> Result<(usize, SocketAddr), ReceiverError> {
+    let (len, addr) = sock.recv_from(buf).await?;
+    let max_size = buf.len().saturating_sub(1);
+    if len > max_size {
+        return Err(ReceiverError::TruncatedDatagram(addr, max_size));
+    }
+
+    Ok((len, addr))
+}
+
 pub async fn run_receiver(
-    bind_host: &str,
-    bind_port: u16,
-    contact_info_sender: Sender<ContactInfo>,
+    source: &Source,
+    datagram_filter: &DatagramFilter,
+    max_datagram_size: usize,
+    contact_event_sender: Sender<ContactEvent>,
 ) -> Result<(), ReceiverError> {
-    let binding = format!("{}:{}", bind_host, bind_port);
-    let sock = UdpSocket::bind(binding).await?;
+    let sock = bind_socket(source).await?;
 
-    let mut buf = [0; 8192];
+    let mut buf = vec![0; max_datagram_size + 1];
 
     loop {
-        let (len, addr) = sock.recv_from(&mut buf).await?;
-        let payload = String::from_utf8(buf[..len].to_vec()).unwrap();
+        let (len, addr) = match recv_datagram(&sock, &mut buf).await {
+            Ok(received) => received,
+            Err(ReceiverError::UDPSocket(e)) => return Err(e.into()),
+            Err(e) => {
+                log::warn!("Failed to receive datagram: {}", e);
+                continue;
+            }
+        };
+
+        let datagram = match datagram_filter.check(&addr, &buf[..len]) {
+            Ok(datagram) => datagram,
+            Err(_) => continue,
+        };
+
+        let payload = match decode_payload(datagram) {
+            Ok(payload) => payload,
+            Err(e) => {
+                log::warn!("Failed to decode datagram from {:?}: {}", addr, e);
+                continue;
+            }
+        };
         log::debug!("Received {} bytes from {:?}: {}", len, addr, &payload);
 
-        let contact_info = parse_contact_info(&payload).await;
-        if contact_info.is_err() {
-            log::warn!(
-                "Failed to parse contact info: {}",
-                contact_info.unwrap_err()
-            );
+        let contact_events = match parse_payload(source.protocol, &payload).await {
+            Ok(contact_events) => contact_events,
+            Err(e) => {
+                log::warn!("Failed to parse contact event: {}", e);
+                continue;
+            }
+        };
+
+        for mut contact_event in contact_events {
+            contact_event.set_source(&source.label);
+            log::info!("Received contact event: {}", &contact_event);
+            if let Err(e) = contact_event_sender.send(contact_event).await {
+                log::warn!("Failed to send contact event: {}", e);
+            };
+        }
+    }
+}
+
+fn decode_payload(datagram: &[u8]) -> Result<String, ReceiverError> {
+    if let Some((encoding, bom_len)) = Encoding::for_bom(datagram) {
+        return decode_with(encoding, &datagram[bom_len..]);
+    }
+
+    if let Some(encoding) = declared_encoding(datagram).filter(|&e| e != UTF_8) {
+        // The XML parser would apply the declared encoding again on the decoded text.
+        let payload = decode_with(encoding, datagram)?;
+        let end = payload.find("?>").map(|p| p + 2).unwrap_or(0);
+        return Ok(payload[end..].trim_start().to_string());
+    }
+
+    if let Ok(payload) = std::str::from_utf8(datagram) {
+        return Ok(payload.to_string());
+    }
+
+    log::debug!("Payload is not valid UTF-8, falling back to Windows-1252");
+    decode_with(WINDOWS_1252, datagram)
+}
+
+fn decode_with(encoding: &'static Encoding, data: &[u8]) -> Result<String, ReceiverError> {
+    let (payload, had_errors) = encoding.decode_without_bom_handling(data);
+    if had_errors {
+        return Err(ReceiverError::Encoding(encoding.name().to_string()));
+    }
+
+    Ok(payload.into_owned())
+}
+
+fn declared_encoding(datagram: &[u8]) -> Option<&'static Encoding> {
+    let head = &datagram[..datagram.len().min(128)];
+    if !head.starts_with(b"<?xml") {
+        return None;
+    }
+
+    let end = head.windows(2).position(|w| w == b"?>")?;
+    let declaration = String::from_utf8_lossy(&head[..end]);
+    let (_, rest) = declaration.split_once("encoding=")?;
+    let quote = rest.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let label = rest[1..].split(quote).next()?;
+
+    Encoding::for_label(label.as_bytes())
+}
+
+async fn parse_payload(
+    protocol: ReceiverProtocol,
+    payload: &str,
+) -> Result<Vec<ContactEvent>, ReceiverError> {
+    match protocol {
+        ReceiverProtocol::Xml => Ok(vec![parse_contact_event(payload).await?]),
+        ReceiverProtocol::Wsjtx => Err(ReceiverError::UnsupportedMessage(
+            "WSJT-X datagrams are binary".to_string(),
+        )),
+        ReceiverProtocol::Adif => Ok(adif::parse_records(payload)?
+            .iter()
+            .map(|record| adif::contact_info_from_record(record, "ADIF"))
+            .collect::<Result<Vec<ContactInfo>, adif::ADIFError>>()?
+            .into_iter()
+            .map(ContactEvent::Insert)
+            .collect()),
+    }
+}
+
+async fn parse_contact_event(payload: &str) -> Result<ContactEvent, ReceiverError> {
+    match root_element(payload) {
+        Some("contactinfo") => Ok(ContactEvent::Insert(parse_contact_info(payload)?)),
+        Some("contactreplace") => Ok(ContactEvent::Replace(parse_contact_info(payload)?)),
+        Some("contactdelete") => {
+            let contact_deletion: RawContactDeletion = serde_xml_rs::from_str(payload)?;
+            Ok(ContactEvent::Delete(ContactDeletion::try_from(
+                contact_deletion,
+            )?))
+        }
+        Some(root) => Err(ReceiverError::UnsupportedMessage(root.to_string())),
+        None => Err(ReceiverError::UnsupportedMessage(
+            "empty payload".to_string(),
+        )),
+    }
+}
+
+fn parse_contact_info(payload: &str) -> Result<ContactInfo, ReceiverError> {
+    let contact_info: RawContactInfo = serde_xml_rs::from_str(payload)?;
+    ContactInfo::try_from(contact_info)
+}
+
+fn root_element(payload: &str) -> Option<&str> {
+    let mut rest = payload;
+    loop {
+        let start = rest.find('<')?;
+        rest = &rest[start + 1..];
+        if rest.starts_with('?') || rest.starts_with('!') {
             continue;
         }
 
-        let contact_info = contact_info?;
-        log::info!("Received contact info: {}", &contact_info);
-        let result = contact_info_sender.send(contact_info).await;
-        if result.is_err() {
-            log::warn!("Failed to send contact info: {}", result.unwrap_err());
-        };
+        let end = rest.find(|c: char| c == '>' || c == '/' || c.is_whitespace())?;
+        return Some(&rest[..end]);
     }
 }
 
-async fn parse_contact_info(payload: &str) -> Result<ContactInfo, ReceiverError> {
-    let contact_info: ContactInfo = serde_xml_rs::from_str(payload)?;
-    Ok(contact_info)
+fn band_from_mhz(value: &str) -> Result<String, ReceiverError> {
+    let mhz: f64 = value
+        .trim()
+        .parse()
+        .map_err(|_| ReceiverError::FieldParsing("band".to_string(), value.to_string()))?;
+
+    Ok(band_name(mhz)
+        .map(|band| band.to_string())
+        .unwrap_or(format!("{}MHz", value.trim())))
+}
+
+pub fn band_from_frequency(frequency: u64) -> String {
+    let mhz = frequency as f64 / 1_000_000.0;
+    band_name(mhz)
+        .map(|band| band.to_string())
+        .unwrap_or(format!("{}MHz", mhz))
+}
+
+fn band_name(mhz: f64) -> Option<&'static str> {
+    let band = match mhz {
+        m if m < 1.0 => "630",
+        m if m < 3.0 => "160",
+        m if m < 4.5 => "80",
+        m if m < 6.0 => "60",
+        m if m < 8.0 => "40",
+        m if m < 12.0 => "30",
+        m if m < 16.0 => "20",
+        m if m < 19.0 => "17",
+        m if m < 22.0 => "15",
+        m if m < 26.0 => "12",
+        m if m < 40.0 => "10",
+        m if m < 60.0 => "6",
+        m if m < 100.0 => "4",
+        m if m < 200.0 => "2",
+        m if m < 300.0 => "1.25",
+        m if m < 600.0 => "70cm",
+        m if m < 1000.0 => "33cm",
+        m if m < 2000.0 => "23cm",
+        m if m < 3000.0 => "13cm",
+        _ => return None,
+    };
+
+    Some(band)
+}
+
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+fn parse_number<T: std::str::FromStr>(
+    field: &str,
+    value: Option<String>,
+) -> Result<Option<T>, ReceiverError> {
+    value
+        .map(|v| {
+            v.parse::<T>()
+                .map_err(|_| ReceiverError::FieldParsing(field.to_string(), v))
+        })
+        .transpose()
+}
+
+fn parse_frequency(field: &str, value: Option<String>) -> Result<Option<u64>, ReceiverError> {
+    Ok(parse_number::<u64>(field, value)?
+        .filter(|&f| f > 0)
+        .map(|f| f * 10))
+}
+
+fn parse_bool(value: Option<String>) -> bool {
+    value
+        .map(|v| v.eq_ignore_ascii_case("true") || v == "1")
+        .unwrap_or(false)
+}
+
+fn parse_timestamp(value: Option<String>) -> Result<Option<DateTime<Utc>>, ReceiverError> {
+    value
+        .map(|v| {
+            NaiveDateTime::parse_from_str(&v, "%Y-%m-%d %H:%M:%S")
+                .map(|t| t.and_utc())
+                .map_err(|_| ReceiverError::FieldParsing("timestamp".to_string(), v))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::receiver::{
+        band_from_mhz, bind_address, decode_payload, interface_index, parse_contact_event,
+        parse_payload, recv_datagram, ContactDeletion, ContactEvent, ContactInfo, ReceiverError,
+        ReceiverProtocol, Source,
+    };
+    use chrono::{TimeZone, Utc};
+    use std::net::{IpAddr, SocketAddr};
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_parse_contact_info_qartest() {
+        let input = "<?xml version=\"1.0\"?>
+<contactinfo>
+<logger>QARTest 14.9.1</logger>
+<contestname>CQ-WW-SSB</contestname>
+<timestamp>2024-10-24 09:00:00</timestamp>
+<mycall>IS0GVH</mycall>
+<band>40</band>
+<txfreq>710000</txfreq>
+<operator>IS0GVH</operator>
+<mode>SSB</mode>
+<call>N0CALL</call>
+<countryprefix>K</countryprefix>
+<wpxprefix>N0</wpxprefix>
+<snt>59</snt>
+<rcv>59</rcv>
+<nr>1234</nr>
+<exch1>4</exch1>
+<exch2></exch2>
+<exch3></exch3>
+<duplicate>True</duplicate>
+<stationname></stationname>
+<points>3</points>
+<id>123456789</id>
+</contactinfo>";
+
+        let expected = ContactInfo {
+            id: Some("123456789".to_string()),
+            source: None,
+            logger: Some("QARTest 14.9.1".to_string()),
+            contest_name: Some("CQ-WW-SSB".to_string()),
+            timestamp: Some(Utc.with_ymd_and_hms(2024, 10, 24, 9, 0, 0).unwrap()),
+            my_call: Some("IS0GVH".to_string()),
+            operator: Some("IS0GVH".to_string()),
+            station_name: None,
+            call: "N0CALL".to_string(),
+            band: "40".to_string(),
+            mode: Some("SSB".to_string()),
+            tx_frequency: Some(7_100_000),
+            rx_frequency: None,
+            country_prefix: Some("K".to_string()),
+            wpx_prefix: Some("N0".to_string()),
+            grid: None,
+            location: None,
+            sent_rst: Some("59".to_string()),
+            received_rst: Some("59".to_string()),
+            serial_number: Some(1234),
+            exchange1: Some("4".to_string()),
+            exchange2: None,
+            exchange3: None,
+            duplicate: true,
+            long_path: false,
+            points: Some(3),
+        };
+
+        let actual = parse_contact_event(input).await.unwrap();
+
+        assert_eq!(actual, ContactEvent::Insert(expected));
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_info_n1mm() {
+        let input = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<contactinfo>
+<app>N1MM</app>
+<contestname>CWOPS</contestname>
+<contestnr>73</contestnr>
+<timestamp>2020-01-17 16:43:38</timestamp>
+<mycall>W2XYZ</mycall>
+<band>14</band>
+<rxfreq>1402519</rxfreq>
+<txfreq>1402519</txfreq>
+<operator></operator>
+<mode>CW</mode>
+<call>w1aw</call>
+<countryprefix>K</countryprefix>
+<wpxprefix>W1</wpxprefix>
+<stationprefix>W2XYZ</stationprefix>
+<continent>NA</continent>
+<snt>599</snt>
+<sntnr>5</sntnr>
+<rcv>599</rcv>
+<rcvnr>0</rcvnr>
+<gridsquare></gridsquare>
+<exchange1>HIRAM</exchange1>
+<section></section>
+<points>1</points>
+<IsOriginal>False</IsOriginal>
+<StationName>CONTEST-PC</StationName>
+<ID>f9ffac4fcd3e479ca86e137df1338531</ID>
+</contactinfo>";
+
+        let expected = ContactInfo {
+            id: Some("f9ffac4fcd3e479ca86e137df1338531".to_string()),
+            source: None,
+            logger: Some("N1MM".to_string()),
+            contest_name: Some("CWOPS".to_string()),
+            timestamp: Some(Utc.with_ymd_and_hms(2020, 1, 17, 16, 43, 38).unwrap()),
+            my_call: Some("W2XYZ".to_string()),
+            operator: None,
+            station_name: Some("CONTEST-PC".to_string()),
+            call: "W1AW".to_string(),
+            band: "20".to_string(),
+            mode: Some("CW".to_string()),
+            tx_frequency: Some(14_025_190),
+            rx_frequency: Some(14_025_190),
+            country_prefix: Some("K".to_string()),
+            wpx_prefix: Some("W1".to_string()),
+            grid: None,
+            location: None,
+            sent_rst: Some("599".to_string()),
+            received_rst: Some("599".to_string()),
+            serial_number: None,
+            exchange1: Some("HIRAM".to_string()),
+            exchange2: None,
+            exchange3: None,
+            duplicate: false,
+            long_path: false,
+            points: Some(1),
+        };
+
+        let actual = parse_contact_event(input).await.unwrap();
+
+        assert_eq!(actual, ContactEvent::Insert(expected));
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_info_detection() {
+        let input = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<contactinfo>
+<logger>QARTest</logger>
+<!-- <app>N1MM</app> -->
+<call>i0abc</call>
+<band>20</band>
+<txfreq>1402519</txfreq>
+<exch1>&lt;app&gt;</exch1>
+</contactinfo>";
+
+        let ContactEvent::Insert(actual) = parse_contact_event(input).await.unwrap() else {
+            panic!("expected an insert");
+        };
+        assert_eq!(actual.logger, Some("QARTest".to_string()));
+        assert_eq!(actual.band, "20");
+        assert_eq!(actual.exchange1, Some("<app>".to_string()));
+        assert_eq!(actual.rx_frequency, None);
+
+        let input = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<contactinfo>
+<app >N1MM</app >
+<call>w1aw</call>
+<band>14</band>
+<rxfreq>1402519</rxfreq>
+</contactinfo>";
+
+        let ContactEvent::Insert(actual) = parse_contact_event(input).await.unwrap() else {
+            panic!("expected an insert");
+        };
+        assert_eq!(actual.logger, Some("N1MM".to_string()));
+        assert_eq!(actual.band, "20");
+        assert_eq!(actual.rx_frequency, Some(14_025_190));
+
+        let input =
+            "<contactinfo><call>w1aw</call><band>7</band><rxfreq>702519</rxfreq></contactinfo>";
+
+        let ContactEvent::Insert(actual) = parse_contact_event(input).await.unwrap() else {
+            panic!("expected an insert");
+        };
+        assert_eq!(actual.band, "40");
+        assert_eq!(actual.rx_frequency, Some(7_025_190));
+    }
+
+    #[tokio::test]
+    async fn test_parse_payload_adif() {
+        let input = "<CALL:5>K1ABC <BAND:3>20m <MODE:3>SSB <FREQ:6>14.250 <QSO_DATE:8>20241024 <TIME_ON:4>0900
+<LAT:11>N041 30.000 <LON:11>W072 45.000 <EOR>
+<CALL:4>W1AW <BAND:3>40m <MODE:2>CW <QSO_DATE:8>20241024 <TIME_ON:4>0902 <EOR>";
+
+        let actual = parse_payload(ReceiverProtocol::Adif, input).await.unwrap();
+
+        assert_eq!(actual.len(), 2);
+        match &actual[0] {
+            ContactEvent::Insert(contact_info) => {
+                assert_eq!(contact_info.call, "K1ABC");
+                assert_eq!(contact_info.id, Some("K1ABC-20241024090000".to_string()));
+                let location = contact_info.location.unwrap();
+                assert_eq!(location.latitude, 41.5);
+                assert_eq!(location.longitude, -72.75);
+            }
+            other => panic!("Unexpected event: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_payload() {
+        assert_eq!(
+            decode_payload(b"\xef\xbb\xbf<call>IS0GVH</call>").unwrap(),
+            "<call>IS0GVH</call>"
+        );
+        assert_eq!(
+            decode_payload(b"<name>Citt\xe0</name>").unwrap(),
+            "<name>Città</name>"
+        );
+        assert_eq!(
+            decode_payload(b"<?xml version=\"1.0\" encoding=\"ISO-8859-15\"?><name>\xa4</name>")
+                .unwrap(),
+            "<name>€</name>"
+        );
+        assert_eq!(decode_payload(b"\xff\xfe<\x00a\x00>\x00").unwrap(), "<a>");
+        assert!(decode_payload(b"\xff\xfe<\x00a\x00>").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_event_latin1() {
+        let payload = decode_payload(
+            b"<?xml version=\"1.0\" encoding=\"ISO-8859-1\"?>
+<contactinfo><call>IS0GVH</call><band>20</band><operator>Nicol\xf2</operator></contactinfo>",
+        )
+        .unwrap();
+
+        match parse_contact_event(&payload).await.unwrap() {
+            ContactEvent::Insert(contact_info) => {
+                assert_eq!(contact_info.operator, Some("Nicolò".to_string()))
+            }
+            other => panic!("Unexpected event: {}", other),
+        }
+    }
+
+    #[test]
+    fn test_source_from_str() {
+        let actual: Source = "wsjtx://[::]:2237#FT8".parse().unwrap();
+        assert_eq!(
+            actual,
+            Source {
+                protocol: ReceiverProtocol::Wsjtx,
+                host: "::".to_string(),
+                port: 2237,
+                label: Some("FT8".to_string()),
+                multicast_group: None,
+                multicast_interface: None,
+                reuse: false,
+            }
+        );
+        assert_eq!(actual.to_string(), "wsjtx://[::]:2237#FT8");
+
+        let actual: Source =
+            "xml://0.0.0.0:12060?group=239.255.0.1&interface=192.168.1.10&reuse#RUN"
+                .parse()
+                .unwrap();
+        assert_eq!(actual.multicast_group, Some("239.255.0.1".parse().unwrap()));
+        assert_eq!(actual.multicast_interface, Some("192.168.1.10".to_string()));
+        assert!(actual.reuse);
+        assert_eq!(
+            actual.to_string(),
+            "xml://0.0.0.0:12060?group=239.255.0.1&interface=192.168.1.10&reuse=true#RUN"
+        );
+
+        assert!("xml://0.0.0.0:12060?group=10.0.0.1"
+            .parse::<Source>()
+            .is_err());
+        assert!("xml://0.0.0.0:12060?ttl=1".parse::<Source>().is_err());
+
+        let actual: Source = "xml://0.0.0.0:12060".parse().unwrap();
+        assert_eq!(actual.host, "0.0.0.0");
+        assert_eq!(actual.label, None);
+
+        assert!("0.0.0.0:12060".parse::<Source>().is_err());
+        assert!("ftp://0.0.0.0:12060".parse::<Source>().is_err());
+        assert!("xml://0.0.0.0".parse::<Source>().is_err());
+    }
+
+    #[test]
+    fn test_bind_address() {
+        let any_v6: SocketAddr = "[::]:12060".parse().unwrap();
+        let any_v4: SocketAddr = "0.0.0.0:12060".parse().unwrap();
+        let local_v6: SocketAddr = "[::1]:12060".parse().unwrap();
+        let local_v4: SocketAddr = "127.0.0.1:12060".parse().unwrap();
+        let group_v4: IpAddr = "239.255.0.1".parse().unwrap();
+        let group_v6: IpAddr = "ff02::1".parse().unwrap();
+
+        assert_eq!(bind_address("::", &[any_v6], None).unwrap(), any_v6);
+        assert_eq!(
+            bind_address("::", &[any_v6], Some(group_v4)).unwrap(),
+            any_v4
+        );
+        assert_eq!(
+            bind_address("0.0.0.0", &[any_v4], Some(group_v6)).unwrap(),
+            any_v6
+        );
+        assert_eq!(
+            bind_address("localhost", &[local_v6, local_v4], Some(group_v4)).unwrap(),
+            local_v4
+        );
+        assert!(bind_address("::1", &[local_v6], Some(group_v4)).is_err());
+        assert!(bind_address("nowhere", &[], None).is_err());
+    }
+
+    #[test]
+    fn test_interface_index() {
+        assert_eq!(interface_index("3").unwrap(), 3);
+        #[cfg(target_os = "linux")]
+        assert_eq!(interface_index("lo").unwrap(), 1);
+        assert!(interface_index("no-such-interface0").is_err());
+        assert!(interface_index("eth\0").is_err());
+    }
+
+    #[test]
+    fn test_band_from_mhz() {
+        assert_eq!(band_from_mhz("1.8").unwrap(), "160");
+        assert_eq!(band_from_mhz("3.5").unwrap(), "80");
+        assert_eq!(band_from_mhz("7").unwrap(), "40");
+        assert_eq!(band_from_mhz("28").unwrap(), "10");
+        assert_eq!(band_from_mhz("144").unwrap(), "2");
+        assert_eq!(band_from_mhz("432").unwrap(), "70cm");
+        assert!(band_from_mhz("forty").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_info_invalid_timestamp() {
+        let input = "<contactinfo>
+<timestamp>yesterday</timestamp>
+<band>40</band>
+<call>N0CALL</call>
+</contactinfo>";
+
+        let actual = parse_contact_event(input).await;
+
+        assert!(actual.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_event_replace() {
+        let input = "<contactreplace>
+<timestamp>2024-10-24 09:00:00</timestamp>
+<band>40</band>
+<call>N0CALL</call>
+<id>123456789</id>
+</contactreplace>";
+
+        let actual = parse_contact_event(input).await.unwrap();
+
+        match actual {
+            ContactEvent::Replace(contact_info) => {
+                assert_eq!(contact_info.id, Some("123456789".to_string()));
+                assert_eq!(contact_info.call, "N0CALL");
+            }
+            _ => panic!("Unexpected event: {}", actual),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parse_contact_event_delete() {
+        let input = "<?xml version=\"1.0\" encoding=\"utf-8\"?>
+<contactdelete>
+<app>N1MM</app>
+<timestamp>2020-01-17 16:43:38</timestamp>
+<call>W1AW</call>
+<contestnr>73</contestnr>
+<StationName>CONTEST-PC</StationName>
+<ID>f9ffac4fcd3e479ca86e137df1338531</ID>
+</contactdelete>";
+
+        let expected = ContactEvent::Delete(ContactDeletion {
+            id: "f9ffac4fcd3e479ca86e137df1338531".to_string(),
+            call: Some("W1AW".to_string()),
+        });
+
+        let actual = parse_contact_event(input).await.unwrap();
+
+        assert_eq!(actual, expected);
+    }
+
+    #[tokio::test]
+    async fn test_recv_datagram_truncated() {
+        let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let mut buf = vec![0; 9];
+
+        sender.send_to(&[1; 16], addr).await.unwrap();
+        match recv_datagram(&receiver, &mut buf).await {
+            Err(ReceiverError::TruncatedDatagram(from, max_size)) => {
+                assert_eq!(from, sender.local_addr().unwrap());
+                assert_eq!(max_size, 8);
+            }
+            other => panic!("Unexpected result: {:?}", other),
+        }
+
+        sender.send_to(&[2; 8], addr).await.unwrap();
+        let (len, _) = recv_datagram(&receiver, &mut buf).await.unwrap();
+        assert_eq!(&buf[..len], &[2; 8]);
+    }
 }
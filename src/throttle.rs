@@ -0,0 +1,254 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::callbook::{CallbookError, CallbookProvider, CallbookRecord};
+use async_trait::async_trait;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub const MIN_RATE_LIMIT: f64 = 0.001;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ThrottleSettings {
+    pub rate_limit: f64,
+    pub burst: u32,
+    pub failure_threshold: u32,
+    pub probe_interval: Duration,
+}
+
+struct BucketState {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+pub struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    state: Mutex<BucketState>,
+}
+
+impl TokenBucket {
+    pub fn new(rate: f64, burst: u32, now: Instant) -> Self {
+        let capacity = burst.max(1) as f64;
+        Self {
+            rate,
+            capacity,
+            state: Mutex::new(BucketState {
+                tokens: capacity,
+                updated_at: now,
+            }),
+        }
+    }
+
+    pub fn reserve(&self, now: Instant) -> Duration {
+        let mut state = self.state.lock().unwrap();
+
+        let elapsed = now.saturating_duration_since(state.updated_at);
+        state.tokens = (state.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        state.updated_at = state.updated_at.max(now);
+        state.tokens -= 1.0;
+
+        match state.tokens < 0.0 {
+            true => Duration::from_secs_f64(-state.tokens / self.rate),
+            false => Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CircuitState {
+    Closed { failures: u32 },
+    Open { until: Instant },
+    HalfOpen { until: Instant },
+}
+
+pub struct CircuitBreaker {
+    failure_threshold: u32,
+    probe_interval: Duration,
+    state: Mutex<CircuitState>,
+}
+
+impl CircuitBreaker {
+    pub fn new(failure_threshold: u32, probe_interval: Duration) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            probe_interval,
+            state: Mutex::new(CircuitState::Closed { failures: 0 }),
+        }
+    }
+
+    pub fn allow(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match *state {
+            CircuitState::Closed { .. } => true,
+            CircuitState::Open { until } | CircuitState::HalfOpen { until } if now >= until => {
+                *state = CircuitState::HalfOpen {
+                    until: now + self.probe_interval,
+                };
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn record_success(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let recovered = !matches!(*state, CircuitState::Closed { .. });
+        *state = CircuitState::Closed { failures: 0 };
+        recovered
+    }
+
+    pub fn record_failure(&self, now: Instant) -> bool {
+        let mut state = self.state.lock().unwrap();
+        let failures = match *state {
+            CircuitState::Closed { failures } => failures + 1,
+            _ => self.failure_threshold,
+        };
+
+        match failures >= self.failure_threshold {
+            true => {
+                *state = CircuitState::Open {
+                    until: now + self.probe_interval,
+                };
+                true
+            }
+            false => {
+                *state = CircuitState::Closed { failures };
+                false
+            }
+        }
+    }
+}
+
+pub struct ThrottledProvider {
+    provider: Box<dyn CallbookProvider>,
+    token_bucket: Option<TokenBucket>,
+    circuit_breaker: CircuitBreaker,
+}
+
+impl ThrottledProvider {
+    pub fn new(provider: Box<dyn CallbookProvider>, settings: ThrottleSettings) -> Self {
+        let token_bucket = match settings.rate_limit > 0.0 {
+            true => Some(TokenBucket::new(
+                settings.rate_limit,
+                settings.burst,
+                Instant::now(),
+            )),
+            false => None,
+        };
+
+        Self {
+            provider,
+            token_bucket,
+            circuit_breaker: CircuitBreaker::new(
+                settings.failure_threshold,
+                settings.probe_interval,
+            ),
+        }
+    }
+}
+
+#[async_trait]
+impl CallbookProvider for ThrottledProvider {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    async fn lookup(&self, callsign: &str) -> Result<CallbookRecord, CallbookError> {
+        if !self.circuit_breaker.allow(Instant::now()) {
+            return Err(CallbookError::Unavailable(self.name().to_string()));
+        }
+
+        if let Some(token_bucket) = &self.token_bucket {
+            let delay = token_bucket.reserve(Instant::now());
+            if !delay.is_zero() {
+                log::debug!(
+                    "Rate limiting {} lookup of {} by {:?}",
+                    self.name(),
+                    callsign,
+                    delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+
+        let result = self.provider.lookup(callsign).await;
+        match &result {
+            Err(e) if e.is_transient() => {
+                if self.circuit_breaker.record_failure(Instant::now()) {
+                    log::warn!(
+                        "{} is failing ({}), using fallback locations until it recovers",
+                        self.name(),
+                        e
+                    );
+                }
+            }
+            _ => {
+                if self.circuit_breaker.record_success() {
+                    log::info!("{} recovered", self.name());
+                }
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::throttle::{CircuitBreaker, TokenBucket};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let token_bucket = TokenBucket::new(2.0, 2, now);
+
+        assert_eq!(token_bucket.reserve(now), Duration::ZERO);
+        assert_eq!(token_bucket.reserve(now), Duration::ZERO);
+        assert_eq!(token_bucket.reserve(now), Duration::from_millis(500));
+        assert_eq!(token_bucket.reserve(now), Duration::from_secs(1));
+
+        let later = now + Duration::from_secs(10);
+        assert_eq!(token_bucket.reserve(later), Duration::ZERO);
+        assert_eq!(token_bucket.reserve(later), Duration::ZERO);
+        assert_eq!(token_bucket.reserve(later), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let now = Instant::now();
+        let circuit_breaker = CircuitBreaker::new(2, Duration::from_secs(30));
+
+        assert!(circuit_breaker.allow(now));
+        assert!(!circuit_breaker.record_failure(now));
+        assert!(circuit_breaker.allow(now));
+        assert!(circuit_breaker.record_failure(now));
+        assert!(!circuit_breaker.allow(now + Duration::from_secs(10)));
+
+        let probe = now + Duration::from_secs(30);
+        assert!(circuit_breaker.allow(probe));
+        assert!(!circuit_breaker.allow(probe));
+        assert!(circuit_breaker.record_failure(probe));
+        assert!(!circuit_breaker.allow(probe + Duration::from_secs(10)));
+
+        let probe = probe + Duration::from_secs(30);
+        assert!(circuit_breaker.allow(probe));
+        assert!(circuit_breaker.record_success());
+        assert!(circuit_breaker.allow(probe));
+        assert!(!circuit_breaker.record_success());
+    }
+}
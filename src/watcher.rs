@@ -0,0 +1,316 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::adif;
+use crate::cabrillo;
+use crate::receiver::{ContactEvent, ContactInfo};
+use async_channel::Sender;
+use clap::ValueEnum;
+use std::fmt::{Display, Formatter};
+use std::fs::Metadata;
+use std::io::{ErrorKind, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum LogFormat {
+    Adif,
+    Cabrillo,
+}
+
+impl LogFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .as_deref()
+        {
+            Some("adi") | Some("adif") => LogFormat::Adif,
+            _ => LogFormat::Cabrillo,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WatcherError {
+    IO(std::io::Error),
+}
+
+impl Display for WatcherError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WatcherError::IO(e) => {
+                write!(f, "IO error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for WatcherError {
+    fn from(value: std::io::Error) -> Self {
+        Self::IO(value)
+    }
+}
+
+struct LogParser {
+    format: LogFormat,
+    header: cabrillo::Header,
+    pending: Vec<u8>,
+}
+
+impl LogParser {
+    fn new(format: LogFormat) -> Self {
+        Self {
+            format,
+            header: cabrillo::Header::default(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.header = cabrillo::Header::default();
+        self.pending.clear();
+    }
+
+    fn feed(&mut self, data: &[u8]) -> Vec<ContactInfo> {
+        self.pending.extend_from_slice(data);
+
+        let end = match self.format {
+            LogFormat::Adif => adif::records_end(&self.pending),
+            LogFormat::Cabrillo => self
+                .pending
+                .iter()
+                .rposition(|&b| b == b'\n')
+                .map(|p| p + 1),
+        };
+        let end = match end {
+            Some(end) => end,
+            None => return Vec::new(),
+        };
+
+        let complete: Vec<u8> = self.pending.drain(..end).collect();
+        let complete = String::from_utf8_lossy(&complete);
+
+        match self.format {
+            LogFormat::Adif => self.parse_adif(&complete),
+            LogFormat::Cabrillo => self.parse_cabrillo(&complete),
+        }
+    }
+
+    fn parse_adif(&self, data: &str) -> Vec<ContactInfo> {
+        let records = match adif::parse_records(data) {
+            Ok(records) => records,
+            Err(e) => {
+                log::warn!("Failed to parse ADIF records: {}", e);
+                return Vec::new();
+            }
+        };
+
+        records
+            .iter()
+            .filter_map(
+                |record| match adif::contact_info_from_record(record, "ADIF") {
+                    Ok(contact_info) => Some(contact_info),
+                    Err(e) => {
+                        log::warn!("Failed to parse ADIF record: {}", e);
+                        None
+                    }
+                },
+            )
+            .collect()
+    }
+
+    fn parse_cabrillo(&mut self, data: &str) -> Vec<ContactInfo> {
+        data.lines()
+            .filter_map(|line| match cabrillo::parse_line(line, &mut self.header) {
+                Ok(contact_info) => contact_info,
+                Err(e) => {
+                    log::warn!("Failed to parse Cabrillo line: {}", e);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+pub async fn run_file_watcher(
+    path: &Path,
+    format: LogFormat,
+    contact_event_sender: Sender<ContactEvent>,
+) -> Result<(), WatcherError> {
+    let mut parser = LogParser::new(format);
+    let mut offset: u64 = 0;
+    let mut identity: Option<(u64, u64)> = None;
+    let mut skip_existing = true;
+
+    loop {
+        match tokio::fs::metadata(path).await {
+            Ok(metadata) => {
+                let current_identity = file_identity(&metadata);
+                if identity.is_some() && current_identity != identity {
+                    log::info!("Log file {} rotated", path.display());
+                    offset = 0;
+                    parser.reset();
+                } else if metadata.len() < offset {
+                    log::info!("Log file {} truncated", path.display());
+                    offset = 0;
+                    parser.reset();
+                }
+                identity = current_identity;
+
+                if metadata.len() > offset {
+                    match read_from(path, offset).await {
+                        Ok(data) => {
+                            offset += data.len() as u64;
+                            let contacts = parser.feed(&data);
+                            if skip_existing {
+                                log::debug!(
+                                    "Skipping {} contacts already in {}",
+                                    contacts.len(),
+                                    path.display()
+                                );
+                            } else {
+                                send_contacts(contacts, &contact_event_sender).await;
+                            }
+                        }
+                        Err(WatcherError::IO(e)) if e.kind() == ErrorKind::NotFound => {
+                            log::warn!("Failed to read log file {}: {}", path.display(), e);
+                        }
+                        Err(e) => return Err(e),
+                    }
+                }
+            }
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.into()),
+            Err(e) => {
+                log::debug!("Log file {} not available: {}", path.display(), e);
+                identity = None;
+                offset = 0;
+                parser.reset();
+            }
+        }
+
+        skip_existing = false;
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn read_from(path: &Path, offset: u64) -> Result<Vec<u8>, WatcherError> {
+    let mut file = File::open(path).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).await?;
+
+    Ok(data)
+}
+
+async fn send_contacts(contacts: Vec<ContactInfo>, contact_event_sender: &Sender<ContactEvent>) {
+    for contact_info in contacts {
+        log::info!("Received contact info: {}", &contact_info);
+        if let Err(e) = contact_event_sender
+            .send(ContactEvent::Insert(contact_info))
+            .await
+        {
+            log::warn!("Failed to send contact event: {}", e);
+        };
+    }
+}
+
+#[cfg(unix)]
+fn file_identity(metadata: &Metadata) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn file_identity(_metadata: &Metadata) -> Option<(u64, u64)> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::watcher::{run_file_watcher, LogFormat, LogParser};
+    use std::path::Path;
+    use std::time::Duration;
+
+    #[test]
+    fn test_log_format_from_path() {
+        assert_eq!(LogFormat::from_path(Path::new("log.ADI")), LogFormat::Adif);
+        assert_eq!(
+            LogFormat::from_path(Path::new("log.log")),
+            LogFormat::Cabrillo
+        );
+    }
+
+    #[test]
+    fn test_log_parser_adif_partial_records() {
+        let mut parser = LogParser::new(LogFormat::Adif);
+
+        let actual = parser.feed(b"<EOH>\n<CALL:4>W1AW <BAND:3>20m <E");
+        assert!(actual.is_empty());
+
+        let actual = parser.feed(b"OR>\n<CALL:5>K1");
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].call, "W1AW");
+
+        let actual = parser.feed(b"ABC <BAND:3>40m <EOR>\n");
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].call, "K1ABC");
+
+        let actual = parser.feed(b"<CALL:4>W1AW <BAND:3>20m <COMMENT:12>Worked <EOR>");
+        assert!(actual.is_empty());
+
+        let actual = parser.feed(b" <EOR>\n");
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].call, "W1AW");
+    }
+
+    #[test]
+    fn test_log_parser_cabrillo_partial_lines() {
+        let mut parser = LogParser::new(LogFormat::Cabrillo);
+
+        let actual = parser.feed(
+            b"START-OF-LOG: 3.0\nCONTEST: CQ-WW-CW\nQSO: 7005 CW 2024-11-23 0711 IS0GVH 599 15 W1",
+        );
+        assert!(actual.is_empty());
+
+        let actual = parser.feed(b"AW 599 05\n");
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].call, "W1AW");
+        assert_eq!(actual[0].contest_name, Some("CQ-WW-CW".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_run_file_watcher_fatal_error() {
+        let file =
+            std::env::temp_dir().join(format!("live-qso-map-watch-{}.adi", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let (contact_event_sender, _contact_event_receiver) = async_channel::unbounded();
+
+        let result = tokio::time::timeout(
+            Duration::from_secs(5),
+            run_file_watcher(&file.join("log.adi"), LogFormat::Adif, contact_event_sender),
+        )
+        .await;
+        assert!(result.is_ok_and(|result| result.is_err()));
+
+        std::fs::remove_file(file).unwrap();
+    }
+}
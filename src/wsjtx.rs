@@ -0,0 +1,417 @@
+/*
+ * Copyright (C) 2024 Luca Cireddu <sardylan@gmail.com>
+ *
+ * This program is free software: you can redistribute it and/or modify it under
+ * the terms of the GNU General Public License as published by the Free Software
+ * Foundation, version 3.
+ *
+ * This program is distributed in the hope that it will be useful, but WITHOUT
+ * ANY WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS
+ * FOR A PARTICULAR PURPOSE. See the GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License along with
+ * this program. If not, see <https://www.gnu.org/licenses/>.
+ *
+ */
+
+use crate::adif;
+use crate::filter::DatagramFilter;
+use crate::receiver::{
+    band_from_frequency, bind_socket, recv_datagram, ContactEvent, ContactInfo, ReceiverError,
+    Source,
+};
+use async_channel::Sender;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::fmt::{Display, Formatter};
+
+const MAGIC: u32 = 0xadbccbda;
+
+const MESSAGE_QSO_LOGGED: u32 = 5;
+const MESSAGE_LOGGED_ADIF: u32 = 12;
+
+const JULIAN_DAY_CE_OFFSET: i64 = 1_721_425;
+
+#[derive(Debug)]
+pub enum WSJTXError {
+    UDPSocket(std::io::Error),
+    Decoding(String),
+    ADIFParsing(adif::ADIFError),
+    QueueSenderError(Box<async_channel::SendError<ContactEvent>>),
+}
+
+impl Display for WSJTXError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WSJTXError::UDPSocket(e) => {
+                write!(f, "UDP Socket error: {}", e)
+            }
+            WSJTXError::Decoding(e) => {
+                write!(f, "Decoding error: {}", e)
+            }
+            WSJTXError::ADIFParsing(e) => {
+                write!(f, "ADIF Parsing error: {}", e)
+            }
+            WSJTXError::QueueSenderError(e) => {
+                write!(f, "Queue sender error: {}", e)
+            }
+        }
+    }
+}
+
+impl From<std::io::Error> for WSJTXError {
+    fn from(value: std::io::Error) -> Self {
+        Self::UDPSocket(value)
+    }
+}
+
+impl From<adif::ADIFError> for WSJTXError {
+    fn from(value: adif::ADIFError) -> Self {
+        Self::ADIFParsing(value)
+    }
+}
+
+impl From<async_channel::SendError<ContactEvent>> for WSJTXError {
+    fn from(value: async_channel::SendError<ContactEvent>) -> Self {
+        Self::QueueSenderError(Box::new(value))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Message {
+    QSOLogged(Box<ContactInfo>),
+    LoggedADIF(Vec<ContactInfo>),
+    Other(u32),
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+
+    fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], WSJTXError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(WSJTXError::Decoding(format!(
+                "unexpected end of message at byte {}",
+                self.pos
+            )))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, WSJTXError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u32(&mut self) -> Result<u32, WSJTXError> {
+        Ok(u32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, WSJTXError> {
+        Ok(i32::from_be_bytes(self.read_bytes(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, WSJTXError> {
+        Ok(u64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_i64(&mut self) -> Result<i64, WSJTXError> {
+        Ok(i64::from_be_bytes(self.read_bytes(8)?.try_into().unwrap()))
+    }
+
+    fn read_utf8(&mut self) -> Result<Option<String>, WSJTXError> {
+        let len = self.read_u32()?;
+        if len == u32::MAX {
+            return Ok(None);
+        }
+
+        let value = String::from_utf8_lossy(self.read_bytes(len as usize)?)
+            .trim()
+            .to_string();
+        Ok(Some(value).filter(|v| !v.is_empty()))
+    }
+
+    fn read_optional_utf8(&mut self) -> Result<Option<String>, WSJTXError> {
+        if self.is_empty() {
+            return Ok(None);
+        }
+        self.read_utf8()
+    }
+
+    fn read_datetime(&mut self) -> Result<Option<DateTime<Utc>>, WSJTXError> {
+        let julian_day = self.read_i64()?;
+        let msecs = self.read_u32()?;
+        let offset = match self.read_u8()? {
+            2 => self.read_i32()?,
+            3 => {
+                let len = self.read_u32()?;
+                if len != u32::MAX {
+                    self.read_bytes(len as usize)?;
+                }
+                0
+            }
+            _ => 0,
+        };
+
+        if msecs == u32::MAX {
+            return Ok(None);
+        }
+
+        let date = i32::try_from(julian_day - JULIAN_DAY_CE_OFFSET)
+            .ok()
+            .and_then(NaiveDate::from_num_days_from_ce_opt);
+        Ok(date.map(|date| {
+            date.and_hms_opt(0, 0, 0).unwrap().and_utc() + Duration::milliseconds(msecs as i64)
+                - Duration::seconds(offset as i64)
+        }))
+    }
+}
+
+pub async fn run_wsjtx_receiver(
+    source: &Source,
+    datagram_filter: &DatagramFilter,
+    max_datagram_size: usize,
+    contact_event_sender: Sender<ContactEvent>,
+) -> Result<(), WSJTXError> {
+    let sock = bind_socket(source).await?;
+
+    let mut buf = vec![0; max_datagram_size + 1];
+    let mut last_id: Option<String> = None;
+
+    loop {
+        let (len, addr) = match recv_datagram(&sock, &mut buf).await {
+            Ok(received) => received,
+            Err(ReceiverError::UDPSocket(e)) => return Err(e.into()),
+            Err(e) => {
+                log::warn!("Discarding datagram: {}", e);
+                continue;
+            }
+        };
+        log::trace!("Received {} bytes from {:?}", len, addr);
+
+        let datagram = match datagram_filter.check(&addr, &buf[..len]) {
+            Ok(datagram) => datagram,
+            Err(_) => continue,
+        };
+
+        let contacts = match decode_message(datagram) {
+            Ok(Message::QSOLogged(contact_info)) => vec![*contact_info],
+            Ok(Message::LoggedADIF(contacts)) => contacts,
+            Ok(Message::Other(message_type)) => {
+                log::trace!("Ignoring WSJT-X message type {}", message_type);
+                continue;
+            }
+            Err(e) => {
+                log::warn!("Failed to decode WSJT-X message: {}", e);
+                continue;
+            }
+        };
+
+        for mut contact_info in contacts {
+            if contact_info.id.is_some() && contact_info.id == last_id {
+                log::debug!("Skipping already received contact: {}", &contact_info);
+                continue;
+            }
+            last_id.clone_from(&contact_info.id);
+            contact_info.source.clone_from(&source.label);
+
+            log::info!("Received contact info: {}", &contact_info);
+            if let Err(e) = contact_event_sender
+                .send(ContactEvent::Insert(contact_info))
+                .await
+            {
+                log::warn!("Failed to send contact event: {}", e);
+            };
+        }
+    }
+}
+
+pub fn decode_message(data: &[u8]) -> Result<Message, WSJTXError> {
+    let mut reader = Reader::new(data);
+
+    let magic = reader.read_u32()?;
+    if magic != MAGIC {
+        return Err(WSJTXError::Decoding(format!("invalid magic {:#x}", magic)));
+    }
+    let _schema = reader.read_u32()?;
+    let message_type = reader.read_u32()?;
+    let id = reader.read_utf8()?;
+
+    match message_type {
+        MESSAGE_QSO_LOGGED => Ok(Message::QSOLogged(Box::new(decode_qso_logged(
+            &mut reader,
+            id,
+        )?))),
+        MESSAGE_LOGGED_ADIF => {
+            let payload = reader.read_utf8()?.unwrap_or_default();
+            let logger = id.unwrap_or("WSJT-X".to_string());
+            let contacts = adif::parse_records(&payload)?
+                .iter()
+                .map(|record| adif::contact_info_from_record(record, &logger))
+                .collect::<Result<Vec<ContactInfo>, adif::ADIFError>>()?;
+            Ok(Message::LoggedADIF(contacts))
+        }
+        _ => Ok(Message::Other(message_type)),
+    }
+}
+
+fn decode_qso_logged(reader: &mut Reader, id: Option<String>) -> Result<ContactInfo, WSJTXError> {
+    let _time_off = reader.read_datetime()?;
+    let call = reader
+        .read_utf8()?
+        .ok_or(WSJTXError::Decoding("missing DX call".to_string()))?
+        .to_uppercase();
+    let grid = reader.read_utf8()?.map(|g| g.to_uppercase());
+    let tx_frequency = reader.read_u64()?;
+    let mode = reader.read_utf8()?;
+    let sent_rst = reader.read_utf8()?;
+    let received_rst = reader.read_utf8()?;
+    let _tx_power = reader.read_utf8()?;
+    let _comments = reader.read_utf8()?;
+    let _name = reader.read_utf8()?;
+    let time_on = reader.read_datetime()?;
+    let operator = reader.read_optional_utf8()?;
+    let my_call = reader.read_optional_utf8()?;
+    let _my_grid = reader.read_optional_utf8()?;
+    let _exchange_sent = reader.read_optional_utf8()?;
+    let exchange_received = reader.read_optional_utf8()?;
+
+    let mut contact_info = ContactInfo {
+        id: None,
+        source: None,
+        logger: Some(id.unwrap_or("WSJT-X".to_string())),
+        contest_name: None,
+        timestamp: time_on,
+        my_call: my_call.map(|c| c.to_uppercase()),
+        operator: operator.map(|c| c.to_uppercase()),
+        station_name: None,
+        call,
+        band: band_from_frequency(tx_frequency),
+        mode,
+        tx_frequency: Some(tx_frequency).filter(|&f| f > 0),
+        rx_frequency: None,
+        country_prefix: None,
+        wpx_prefix: None,
+        grid,
+        location: None,
+        sent_rst,
+        received_rst,
+        serial_number: None,
+        exchange1: exchange_received,
+        exchange2: None,
+        exchange3: None,
+        duplicate: false,
+        long_path: false,
+        points: None,
+    };
+    contact_info.id = contact_info.synthetic_id();
+
+    Ok(contact_info)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::wsjtx::{decode_message, Message, MAGIC};
+    use chrono::{TimeZone, Utc};
+
+    fn put_utf8(buf: &mut Vec<u8>, value: &str) {
+        buf.extend_from_slice(&(value.len() as u32).to_be_bytes());
+        buf.extend_from_slice(value.as_bytes());
+    }
+
+    fn put_datetime(buf: &mut Vec<u8>, julian_day: i64, msecs: u32) {
+        buf.extend_from_slice(&julian_day.to_be_bytes());
+        buf.extend_from_slice(&msecs.to_be_bytes());
+        buf.push(1);
+    }
+
+    fn header(message_type: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&MAGIC.to_be_bytes());
+        buf.extend_from_slice(&2u32.to_be_bytes());
+        buf.extend_from_slice(&message_type.to_be_bytes());
+        put_utf8(&mut buf, "WSJT-X");
+        buf
+    }
+
+    #[test]
+    fn test_decode_qso_logged() {
+        let mut buf = header(5);
+        put_datetime(&mut buf, 2460608, 32_460_000);
+        put_utf8(&mut buf, "W1AW");
+        put_utf8(&mut buf, "FN31");
+        buf.extend_from_slice(&14_074_512u64.to_be_bytes());
+        put_utf8(&mut buf, "FT8");
+        put_utf8(&mut buf, "-10");
+        put_utf8(&mut buf, "-12");
+        put_utf8(&mut buf, "");
+        put_utf8(&mut buf, "");
+        put_utf8(&mut buf, "");
+        put_datetime(&mut buf, 2460608, 32_400_000);
+        put_utf8(&mut buf, "");
+        put_utf8(&mut buf, "IS0GVH");
+        put_utf8(&mut buf, "JM49");
+
+        let actual = match decode_message(&buf).unwrap() {
+            Message::QSOLogged(contact_info) => contact_info,
+            other => panic!("Unexpected message: {:?}", other),
+        };
+
+        assert_eq!(actual.call, "W1AW");
+        assert_eq!(actual.grid, Some("FN31".to_string()));
+        assert_eq!(actual.band, "20");
+        assert_eq!(actual.tx_frequency, Some(14_074_512));
+        assert_eq!(actual.mode, Some("FT8".to_string()));
+        assert_eq!(actual.my_call, Some("IS0GVH".to_string()));
+        assert_eq!(
+            actual.timestamp,
+            Some(Utc.with_ymd_and_hms(2024, 10, 24, 9, 0, 0).unwrap())
+        );
+        assert_eq!(actual.id, Some("W1AW-20241024090000".to_string()));
+    }
+
+    #[test]
+    fn test_decode_logged_adif() {
+        let mut buf = header(12);
+        put_utf8(
+            &mut buf,
+            "<adif_ver:5>3.1.0 <programid:6>WSJT-X <EOH>
+<call:4>W1AW <gridsquare:4>FN31 <mode:3>FT8 <qso_date:8>20241024 <time_on:6>090000 <band:3>20m <freq:9>14.074512 <eor>",
+        );
+
+        let actual = match decode_message(&buf).unwrap() {
+            Message::LoggedADIF(contacts) => contacts,
+            other => panic!("Unexpected message: {:?}", other),
+        };
+
+        assert_eq!(actual.len(), 1);
+        assert_eq!(actual[0].call, "W1AW");
+        assert_eq!(actual[0].grid, Some("FN31".to_string()));
+        assert_eq!(actual[0].id, Some("W1AW-20241024090000".to_string()));
+    }
+
+    #[test]
+    fn test_decode_heartbeat() {
+        let mut buf = header(0);
+        buf.extend_from_slice(&3u32.to_be_bytes());
+
+        assert_eq!(decode_message(&buf).unwrap(), Message::Other(0));
+    }
+
+    #[test]
+    fn test_decode_invalid_magic() {
+        let buf = vec![0u8; 16];
+
+        assert!(decode_message(&buf).is_err());
+    }
+}